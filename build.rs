@@ -0,0 +1,36 @@
+use std::env;
+use std::process::Command;
+
+/// Expose build information to `hour-calc version`
+fn main() {
+    let git_hash = command_output("git", &["rev-parse", "--short", "HEAD"])
+        .unwrap_or_else(|| "unknown".to_string());
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let rustc_version =
+        command_output(&rustc, &["--version"]).unwrap_or_else(|| "unknown".to_string());
+    // The time of the commit rather than of the build, which only reruns when the commit
+    // changes. It also keeps builds of the same commit reproducible
+    let commit_timestamp = command_output("git", &["log", "-1", "--format=%ct"])
+        .unwrap_or_else(|| "unknown".to_string());
+
+    println!("cargo:rustc-env=BUILD_GIT_HASH={git_hash}");
+    println!("cargo:rustc-env=BUILD_RUSTC_VERSION={rustc_version}");
+    println!("cargo:rustc-env=BUILD_COMMIT_TIMESTAMP={commit_timestamp}");
+    println!(
+        "cargo:rustc-env=BUILD_TARGET={}",
+        env::var("TARGET").unwrap_or_default()
+    );
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+    println!("cargo:rerun-if-env-changed=RUSTC");
+}
+
+/// The trimmed stdout of a command, if it ran successfully
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
@@ -1,9 +1,27 @@
+use chrono::{NaiveDate, NaiveTime, Weekday};
+use chrono_tz::Tz;
 use clap::{Parser, Subcommand, ValueEnum};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+const EXIT_CODES: &str = "\
+Exit codes:
+  0  Success
+  1  Any other error
+  2  Invalid arguments, or the total is below --min-total
+  3  A calendar couldn't be downloaded, or the total exceeds --max-total with --fail-over-max
+  4  A calendar couldn't be parsed
+  5  A file couldn't be read or written
+  6  No events found, unless --summary-only is given. A total below --min-total exits with 2 instead";
 
 #[derive(Debug, Parser)]
+#[command(after_help = EXIT_CODES)]
 pub struct Args {
     #[command(subcommand)]
     pub commands: Commands,
+    /// Don't warn when the config file is stored inside a git repository
+    #[clap(long, global = true)]
+    pub no_git_warning: bool,
 }
 
 #[derive(Debug, Subcommand)]
@@ -12,20 +30,294 @@ pub enum Commands {
         #[command(subcommand)]
         configure_commands: ConfigureCommands,
     },
-    Report {
-        /// The index of the ICS file. Use `hour-calc configure ics list` to see available options
-        #[clap(long, short)]
-        ics_index: usize,
-        /// The month to filter on. 1-12
-        #[clap(long, short)]
-        month: Option<u32>,
-        /// The year to filter on
-        #[clap(long, short)]
-        year: Option<i32>,
+    Report(ReportArgs),
+    /// Generate an invoice PDF for the events of a report
+    Invoice(InvoiceArgs),
+    /// Print the version and build information
+    Version,
+}
 
-        #[clap(long, short, value_enum)]
-        output_format: OutFormat,
-    },
+#[derive(Debug, Clone, clap::Args)]
+pub struct ReportArgs {
+    /// The index of the ICS file. Use `hour-calc configure ics list` to see available options.
+    /// Multiple calendars can be combined with a comma-separated list, e.g. `0,1,2`.
+    /// If omitted, the calendar can be picked interactively
+    #[clap(long, short, value_delimiter = ',', conflicts_with_all = ["tag", "ics_file"])]
+    pub ics_index: Vec<usize>,
+    /// Never prompt to pick a calendar, `--ics-index` is required instead
+    #[clap(long, alias = "no-tty")]
+    pub non_interactive: bool,
+    /// Read the ICS file from this path instead of a configured calendar. Use `-` to read from stdin.
+    /// A `.csv` file written by `--format csv` is read back as well, with its times taken to be in UTC
+    #[clap(long, conflicts_with = "tag")]
+    pub ics_file: Option<String>,
+    /// Report on all calendars with this tag combined, with a subtotal per calendar
+    #[clap(long)]
+    pub tag: Option<String>,
+    /// Report on all configured calendars combined, with a subtotal per calendar
+    #[clap(long, conflicts_with_all = ["ics_index", "tag", "ics_file"])]
+    pub all: bool,
+    /// Keep events with the same UID and start found in multiple calendars,
+    /// instead of counting them once
+    #[clap(long)]
+    pub no_dedup: bool,
+    /// Leave the calendar with this name out of `--all`. Can be given multiple times
+    #[clap(long, requires = "all")]
+    pub exclude_calendar: Vec<String>,
+    /// Show a separate table per calendar, each with its own total, followed by the grand total
+    #[clap(long, conflicts_with_all = ["group_by", "limit"])]
+    pub split_by_calendar: bool,
+    /// The month to filter on. 1-12.
+    /// If no period is given, falls back to the calendar's default month and year
+    #[clap(long, short)]
+    pub month: Option<u32>,
+    /// The year to filter on. When used with `--week`, this is the ISO week year
+    #[clap(long, short)]
+    pub year: Option<i32>,
+    /// Match `--month` in every year, e.g. all Januaries, even if the calendar has a default year
+    #[clap(long, requires = "month", conflicts_with = "year")]
+    pub any_year: bool,
+    /// The ISO week to filter on. 1-53
+    #[clap(long, short, value_parser = clap::value_parser!(u32).range(1..=53))]
+    pub week: Option<u32>,
+    /// The day weeks start on, for `--week` and `--group-by week`. Defaults to the configured day
+    #[clap(long, value_enum)]
+    pub start_of_week: Option<StartOfWeek>,
+    /// Only include events starting on or after this date. Format: YYYY-MM-DD
+    #[clap(long)]
+    pub since: Option<NaiveDate>,
+    /// Only include events starting on or before this date. Format: YYYY-MM-DD
+    #[clap(long)]
+    pub until: Option<NaiveDate>,
+    /// Only include events starting in the first N days of the month, e.g. for a billing cut-off halfway the month
+    #[clap(long, value_parser = clap::value_parser!(u32).range(1..=31))]
+    pub first_n_days: Option<u32>,
+    /// Only include events starting today
+    #[clap(long)]
+    pub today: bool,
+    /// Only include events in the billing period starting on this date, see `--invoice-period-days`.
+    /// For billing cycles which don't follow calendar months. Format: YYYY-MM-DD
+    #[clap(long, conflicts_with_all = ["month", "year", "week", "since", "until", "today", "first_n_days"])]
+    pub invoice_period_start: Option<NaiveDate>,
+    /// The length in days of the billing period starting on `--invoice-period-start`
+    #[clap(long, default_value_t = 30, requires = "invoice_period_start", value_parser = clap::value_parser!(u32).range(1..))]
+    pub invoice_period_days: u32,
+    /// Only include events in this fiscal year, starting in `--fiscal-year-start` of this year
+    #[clap(long, conflicts_with_all = ["month", "year", "week", "since", "until", "today", "first_n_days", "invoice_period_start"])]
+    pub fiscal_year: Option<i32>,
+    /// The month fiscal years start in, e.g. 4 for April to March. 1-12
+    #[clap(long, default_value_t = 1, requires = "fiscal_year", value_parser = clap::value_parser!(u32).range(1..=12))]
+    pub fiscal_year_start: u32,
+    /// Display dates and times in this timezone instead of the one of the calendar, e.g. `America/New_York`
+    #[clap(long)]
+    pub timezone: Option<Tz>,
+    #[clap(long, short, value_enum, default_value_t)]
+    pub output_format: OutFormat,
+    /// Only print the total and the number of events on a single line, e.g. `March 2024: 06:45:00 (8 events)`
+    #[clap(long)]
+    pub summary_only: bool,
+    /// Also show the total of the previous week or month, or year without either, and the change since.
+    /// Requires `--year` and the table output format
+    #[clap(long, alias = "compare-to-previous-period", requires = "year", conflicts_with_all = ["watch", "dry_run", "ics_file", "annotate", "since", "until", "today", "any_year", "invoice_period_start", "fiscal_year"])]
+    pub compare_previous: bool,
+    /// Keep running, refreshing the report every `--interval` seconds until stopped with Ctrl+C
+    #[clap(long, conflicts_with_all = ["annotate", "dry_run"])]
+    pub watch: bool,
+    /// The number of seconds between refreshes of `--watch`
+    #[clap(long, default_value_t = 60, requires = "watch", value_parser = clap::value_parser!(u64).range(1..))]
+    pub interval: u64,
+    /// Render the report using this Handlebars template instead of the output format.
+    /// Use `default` for the bundled template
+    #[clap(long)]
+    pub template: Option<PathBuf>,
+    /// Write the rendered template or CSV to this file instead of stdout
+    #[clap(long)]
+    pub output_file: Option<PathBuf>,
+    /// The character encoding of CSV and Markdown output
+    #[clap(long, value_enum, default_value_t)]
+    pub output_encoding: OutputEncoding,
+    /// The table syntax of Markdown output
+    #[clap(long, value_enum, default_value_t)]
+    pub markdown_dialect: MarkdownDialect,
+    /// Leave out the header row of CSV, Markdown and Org table output, e.g. to append to an existing file
+    #[clap(long)]
+    pub no_header: bool,
+    /// Keep the events in the order they appear in the ICS file, instead of sorting by date
+    #[clap(long)]
+    pub no_sort: bool,
+    /// How to sort the events. Events with the same key are sorted by date
+    #[clap(long, value_enum, default_value_t, conflicts_with = "no_sort")]
+    pub sort_by: SortBy,
+    /// Combine events into a single row per group in the table
+    #[clap(long, value_enum)]
+    pub group_by: Option<GroupBy>,
+    /// Show the `UID` of every event in the table, e.g. to trace an event back to its calendar
+    #[clap(long)]
+    pub show_event_id: bool,
+    /// The columns of the table, overriding the configured ones. Comma-separated
+    #[clap(long, value_enum, value_delimiter = ',')]
+    pub columns: Option<Vec<TableColumn>>,
+    /// Insert a row showing the time between consecutive events in the table, if it exceeds `--gap-threshold`.
+    /// Requires the events to be sorted by date
+    #[clap(long)]
+    pub show_gaps: bool,
+    /// The minimum gap in minutes shown by `--show-gaps`
+    #[clap(long, default_value_t = 30, requires = "show_gaps")]
+    pub gap_threshold: i64,
+    /// Show at most this many events in the table
+    #[clap(long, conflicts_with = "group_by")]
+    pub limit: Option<usize>,
+    /// Skip this many events in the table, use with `--limit` to page through events
+    #[clap(long, default_value_t = 0, conflicts_with = "group_by")]
+    pub offset: usize,
+    /// Also list the individual events of every group
+    #[clap(long, requires = "group_by")]
+    pub expand: bool,
+    /// Replace event titles using the title mappings stored in the configuration
+    #[clap(long)]
+    pub normalize_titles: bool,
+    /// Don't check if the server responded with an ICS content type
+    #[clap(long)]
+    pub force: bool,
+    /// Also show how many minutes were spent in every hour of the day
+    #[clap(long)]
+    pub hourly_breakdown: bool,
+    /// Also show the total per week, flagging weeks with fewer hours than this
+    #[clap(long)]
+    pub week_hours_target: Option<f64>,
+    /// Also show a histogram of the number of events per duration range
+    #[clap(long)]
+    pub histogram: bool,
+    /// The boundaries of the histogram ranges in minutes. Comma-separated
+    #[clap(long, value_delimiter = ',', default_values_t = [30, 60, 120, 240], requires = "histogram")]
+    pub histogram_buckets: Vec<i64>,
+    /// The User-Agent to send when downloading calendars, instead of the configured one
+    #[clap(long)]
+    pub user_agent: Option<String>,
+    /// Don't verify TLS certificates, e.g. for a server with a self-signed certificate.
+    /// Only use this for servers you trust
+    #[clap(long)]
+    pub no_verify_ssl: bool,
+    /// The colours and borders of the tables. Defaults to the configured scheme
+    #[clap(long, value_enum)]
+    pub color_scheme: Option<ColorScheme>,
+    /// Draw table borders with ASCII characters only. Can also be enabled in the config
+    #[clap(long)]
+    pub ascii: bool,
+    /// Warn if more events than this are found without filtering on a period
+    #[clap(long, default_value_t = 500)]
+    pub max_events: usize,
+    /// Only print which calendars were fetched and how many events passed the filters
+    #[clap(long)]
+    pub dry_run: bool,
+    /// Show whether events are meetings, i.e. have multiple attendees
+    #[clap(long)]
+    pub detect_meetings: bool,
+    /// Leave out events with multiple attendees
+    #[clap(long, conflicts_with = "only_meetings")]
+    pub exclude_meetings: bool,
+    /// Only include events with multiple attendees
+    #[clap(long)]
+    pub only_meetings: bool,
+    /// Leave out reminders: events without a duration which contain an alarm
+    #[clap(long)]
+    pub exclude_alarms: bool,
+    /// Only include billable events: those with the `Billable` category,
+    /// or a title starting or ending with the billable marker of their calendar
+    #[clap(long)]
+    pub billable_only: bool,
+    /// Count at most this many hours per day, shortening the last events of longer days, e.g. `8.0`
+    #[clap(long, value_parser = parse_positive_hours)]
+    pub per_day_cap: Option<f64>,
+    /// Exclude events whose title contains any of these words, case-insensitive. Comma-separated
+    #[clap(long, value_delimiter = ',')]
+    pub exclude_keywords: Vec<String>,
+    /// Show the time spent in uninterrupted sessions without meetings of at least 90 minutes
+    #[clap(long)]
+    pub deep_work_hours: bool,
+    /// Prompt for a replacement title of every event before rendering, e.g. a client-facing description.
+    /// Best combined with a period, to keep the number of prompts down
+    #[clap(long)]
+    pub annotate: bool,
+    /// Show the hourly rate implied by this invoice total and the total time
+    #[clap(long, conflicts_with = "hourly_rate")]
+    pub invoice_total: Option<f64>,
+    /// Show the invoice total for the total time at this hourly rate
+    #[clap(long)]
+    pub hourly_rate: Option<f64>,
+    /// The start of the working day, in `--timezone` or the local timezone. Events outside the working hours are marked with ⚠. Format: HH:MM
+    #[clap(long, value_parser = parse_time_of_day, requires = "working_hours_end")]
+    pub working_hours_start: Option<NaiveTime>,
+    /// The end of the working day, see `--working-hours-start`. Format: HH:MM
+    #[clap(long, value_parser = parse_time_of_day, requires = "working_hours_start")]
+    pub working_hours_end: Option<NaiveTime>,
+    /// The client shown in the PDF header. Defaults to the calendar name
+    #[clap(long)]
+    pub client_name: Option<String>,
+    /// The invoice number shown in the PDF header
+    #[clap(long)]
+    pub invoice_number: Option<String>,
+    /// The cost center shown in the PDF header and the CSV and NDJSON output.
+    /// Defaults to the cost center of the calendar
+    #[clap(long)]
+    pub cost_center: Option<String>,
+    /// The invoice date shown in the PDF header. Format: YYYY-MM-DD
+    #[clap(long)]
+    pub invoice_date: Option<NaiveDate>,
+    /// The font size used in the PDF
+    #[clap(long, default_value_t = 12.0)]
+    pub font_size: f32,
+    /// The font used in the PDF. One of the standard PDF fonts, e.g. Helvetica, Times-Roman or Courier
+    #[clap(long, default_value = "Helvetica")]
+    pub font_family: String,
+    /// The paper size of the PDF
+    #[clap(long, value_enum, default_value_t)]
+    pub paper_size: PaperSize,
+    /// The orientation of the pages of the PDF
+    #[clap(long, value_enum, default_value_t)]
+    pub orientation: Orientation,
+    /// Show the description of every event in the PDF, below or next to its times
+    #[clap(long, alias = "include-description-in-pdf")]
+    pub include_description: bool,
+    /// The number of characters descriptions are truncated to, see `--include-description`
+    #[clap(long, default_value_t = 200, requires = "include_description")]
+    pub description_length: usize,
+    /// Open the PDF in the default viewer after generating it
+    #[clap(long)]
+    pub open: bool,
+    /// The language of the labels in the PDF
+    #[clap(long, value_enum, default_value_t)]
+    pub pdf_locale: PdfLocaleName,
+    /// Exit with code 2 if the total is below this duration. Format: HH:MM
+    #[clap(long, value_parser = parse_hhmm)]
+    pub min_total: Option<i64>,
+    /// Print a warning if the total exceeds this duration. Format: HH:MM
+    #[clap(long, value_parser = parse_hhmm)]
+    pub max_total: Option<i64>,
+    /// Exit with code 3 if the total exceeds `--max-total`, instead of only warning
+    #[clap(long, requires = "max_total")]
+    pub fail_over_max: bool,
+    /// Abort if a calendar can't be parsed, instead of skipping it
+    #[clap(long, conflicts_with = "ignore_errors")]
+    pub strict_parse: bool,
+    /// Skip events which can't be parsed with a warning, instead of aborting the report
+    #[clap(long)]
+    pub ignore_errors: bool,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct InvoiceArgs {
+    #[clap(flatten)]
+    pub report: ReportArgs,
+    /// The price per hour, excluding VAT
+    #[clap(long)]
+    pub rate: f64,
+    /// The VAT percentage
+    #[clap(long, default_value_t = 21.0)]
+    pub vat_rate: f64,
+    /// The date the invoice must be paid by. Defaults to 30 days after the invoice date
+    #[clap(long)]
+    pub due_date: Option<NaiveDate>,
 }
 
 #[derive(Debug, Subcommand)]
@@ -34,14 +326,113 @@ pub enum ConfigureCommands {
         #[command(subcommand)]
         ics_commands: IcsCommands,
     },
-    Clear,
+    /// Remove all configuration, or only a single calendar.
+    /// Removing all configuration first makes a backup
+    Clear {
+        /// Only remove the calendar with this name
+        #[clap(long)]
+        calendar: Option<String>,
+        /// Don't ask for confirmation before removing all configuration
+        #[clap(long)]
+        confirm: bool,
+    },
+    /// Map event titles matching the regex pattern to a canonical title, used by `report --normalize-titles`
+    SetTitleMapping {
+        pattern: String,
+        canonical: String,
+    },
+    ListTitleMappings,
+    RemoveTitleMapping {
+        index: usize,
+    },
+    /// Replace the configuration with a backup, e.g. one made by `configure clear`
+    Restore {
+        backup: PathBuf,
+    },
 }
 
 #[derive(Debug, Subcommand)]
 pub enum IcsCommands {
-    List,
-    Add { name: String, link: String },
-    Remove { index: usize },
+    List {
+        /// How to print the calendars
+        #[clap(long, value_enum, default_value_t)]
+        format: ListFormat,
+        /// Also show when the health was last checked, and the results of the last report
+        #[clap(long)]
+        verbose: bool,
+    },
+    Add {
+        name: String,
+        link: String,
+        /// A note on what the calendar is for, shown in `ics list`
+        #[clap(long)]
+        description: Option<String>,
+        /// Tag the calendar, so it can be reported on using `--tag`. Can be repeated
+        #[clap(long = "tag")]
+        tags: Vec<String>,
+        /// The month to report on when `report` is run without a period. 1-12
+        #[clap(long, value_parser = clap::value_parser!(u32).range(1..=12))]
+        default_month: Option<u32>,
+        /// The year to report on when `report` is run without a period
+        #[clap(long)]
+        default_year: Option<i32>,
+        /// The link is a CalDAV calendar collection, log in with this username
+        #[clap(long, requires = "caldav_password")]
+        caldav_username: Option<String>,
+        /// The password for `--caldav-username`. Stored in plain text in the config
+        #[clap(long, requires = "caldav_username")]
+        caldav_password: Option<String>,
+        /// Don't verify the TLS certificate of the link, e.g. when it's self-signed
+        #[clap(long)]
+        no_verify_ssl: bool,
+        /// The cost center of reports on this calendar, see `report --cost-center`
+        #[clap(long)]
+        default_cost_center: Option<String>,
+        /// The prefix or suffix of the titles of billable events, e.g. `[BILLABLE]`, see `report --billable-only`
+        #[clap(long)]
+        billable_marker: Option<String>,
+        /// Fetch and parse the calendar first, showing its first events, and ask to confirm adding it
+        #[clap(long)]
+        test: bool,
+        /// Add the calendar after `--test` without asking
+        #[clap(long, short, requires = "test")]
+        yes: bool,
+    },
+    Remove {
+        index: usize,
+    },
+    /// Change the name, URL, description or billable marker of a calendar
+    Update {
+        index: usize,
+        #[clap(long)]
+        name: Option<String>,
+        #[clap(long)]
+        url: Option<String>,
+        #[clap(long)]
+        description: Option<String>,
+        #[clap(long)]
+        billable_marker: Option<String>,
+    },
+    /// Check whether the URLs of all calendars are reachable
+    Health,
+    /// Copy a calendar, with all its settings, under a new name
+    Duplicate {
+        index: usize,
+        new_name: String,
+    },
+    /// Sort the calendars alphabetically. This changes their indices
+    Sort {
+        /// What to sort the calendars by
+        #[clap(long, value_enum, default_value_t)]
+        by: IcsSortBy,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum IcsSortBy {
+    #[default]
+    Name,
+    Url,
 }
 
 #[derive(Debug, Clone, Default, ValueEnum)]
@@ -49,4 +440,177 @@ pub enum OutFormat {
     #[default]
     Table,
     Pdf,
+    /// Org-mode heading with `CLOCK` entries in its `LOGBOOK`
+    Org,
+    /// Org-mode table
+    OrgTable,
+    Csv,
+    /// Markdown table
+    Markdown,
+    /// A JSON object per line for every event, followed by a summary object
+    Ndjson,
+    /// `timew track` commands to import the events into Timewarrior
+    Timew,
+}
+
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum MarkdownDialect {
+    /// GitHub Flavored Markdown
+    #[default]
+    Github,
+    /// GitLab wikis, which use the same syntax as GitHub
+    Gitlab,
+    /// Confluence wiki markup, with `||Header||` and `|Cell|` rows
+    Confluence,
+}
+
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum ListFormat {
+    #[default]
+    Table,
+    Json,
+    Csv,
+}
+
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum SortBy {
+    #[default]
+    Date,
+    /// Longest first
+    Duration,
+    /// Alphabetically by title
+    Name,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum GroupBy {
+    /// One row per day
+    Date,
+    /// One row per week, see `--start-of-week`
+    Week,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorScheme {
+    /// Rounded borders without colours
+    #[default]
+    Default,
+    /// Coloured headers for dark terminal backgrounds
+    Dark,
+    /// Coloured headers for light terminal backgrounds
+    Light,
+    /// Plain text without borders
+    None,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum TableColumn {
+    Date,
+    Time,
+    Duration,
+    /// The title of the event
+    Summary,
+    Location,
+    Description,
+    /// Whether the event is a meeting. Also shown with `--detect-meetings`
+    Meeting,
+    /// The calendar of the event. Also shown when reporting on multiple calendars
+    Calendar,
+    /// The `UID` of the event. Also shown with `--show-event-id`
+    Uid,
+}
+
+impl TableColumn {
+    /// The columns shown when neither `--columns` nor the config selects any
+    pub const DEFAULT: [Self; 4] = [Self::Date, Self::Time, Self::Duration, Self::Summary];
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+pub enum StartOfWeek {
+    #[default]
+    Monday,
+    Sunday,
+}
+
+impl StartOfWeek {
+    pub fn weekday(self) -> Weekday {
+        match self {
+            Self::Monday => Weekday::Mon,
+            Self::Sunday => Weekday::Sun,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum OutputEncoding {
+    /// UTF-8 without a byte order mark
+    #[default]
+    Utf8,
+    /// UTF-8 with a byte order mark, for Excel on Windows
+    #[value(name = "utf8bom")]
+    Utf8Bom,
+    /// Latin-1, characters which can't be represented are replaced with `?`
+    Latin1,
+}
+
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum PaperSize {
+    #[default]
+    A4,
+    Letter,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum PdfLocaleName {
+    #[default]
+    Nl,
+    En,
+    De,
+    Fr,
+}
+
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum Orientation {
+    #[default]
+    Portrait,
+    Landscape,
+}
+
+/// Parse a duration formatted as HH:MM into seconds.
+/// The hours may exceed 24, e.g. `160:00`
+fn parse_hhmm(input: &str) -> Result<i64, String> {
+    let (hours, minutes) = input
+        .split_once(':')
+        .ok_or_else(|| format!("Expected a duration formatted as HH:MM, got '{input}'"))?;
+
+    let hours: i64 = hours
+        .parse()
+        .map_err(|_| format!("Invalid hours '{hours}'"))?;
+    let minutes: i64 = minutes
+        .parse()
+        .map_err(|_| format!("Invalid minutes '{minutes}'"))?;
+
+    if hours < 0 || !(0..60).contains(&minutes) {
+        return Err(format!("Invalid duration '{input}'"));
+    }
+
+    Ok((hours * 60 + minutes) * 60)
+}
+
+/// Parse a number of hours larger than zero
+fn parse_positive_hours(input: &str) -> Result<f64, String> {
+    match input.parse::<f64>() {
+        Ok(hours) if hours > 0.0 => Ok(hours),
+        _ => Err(format!(
+            "Expected a number of hours larger than 0, got '{input}'"
+        )),
+    }
+}
+
+/// Parse a time of day formatted as HH:MM
+fn parse_time_of_day(input: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(input, "%H:%M")
+        .map_err(|_| format!("Expected a time formatted as HH:MM, got '{input}'"))
 }
@@ -1,20 +1,230 @@
+use crate::args::{ColorScheme, StartOfWeek, TableColumn};
 use cfg_if::cfg_if;
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::Error;
 use color_eyre::Result;
+use indexmap::IndexMap;
+use notify::{Event, RecursiveMode, Watcher};
+use regex::Regex;
+use reqwest::Url;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::env::var;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
 use tokio::fs;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::mpsc;
+use tokio::time::timeout;
+use tracing::{debug, instrument, warn};
 
-#[derive(Default, Debug, Serialize, Deserialize)]
+/// How long to wait for more changes before reloading the config, see [Config::watch]
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// The contents of the config file as last written by [Config::store],
+/// so [Config::watch] can tell the writes of this process from those of others
+static LAST_STORED: Mutex<Option<Vec<u8>>> = Mutex::new(None);
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct ICalConfig {
     pub url: String,
     pub name: String,
+    /// A note on what the calendar is for
+    #[serde(default)]
+    pub description: Option<String>,
+    /// How the calendar is fetched from `url`
+    #[serde(default)]
+    pub source: IcsSource,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// The result of the last `configure ics health` run
+    #[serde(default)]
+    pub last_health_check: Option<HealthStatus>,
+    /// The month to report on when no period is given
+    #[serde(default)]
+    pub default_month: Option<u32>,
+    /// The year to report on when no period is given
+    #[serde(default)]
+    pub default_year: Option<i32>,
+    /// Whether to verify the TLS certificate of `url`. Defaults to true
+    #[serde(default)]
+    pub verify_ssl: Option<bool>,
+    /// Regex patterns and their replacements, applied in order to the title of every event
+    #[serde(default)]
+    pub normalizations: Vec<(String, String)>,
+    /// How to authenticate when downloading `url`
+    #[serde(default)]
+    pub auth: Option<AuthMethod>,
+    /// The last access token obtained for [AuthMethod::OAuth2]
+    #[serde(default)]
+    pub cached_token: Option<CachedToken>,
+    /// The URL `url` redirected to in the last report, if it did. See [ICalConfig::request_url]
+    #[serde(default)]
+    pub resolved_url: Option<String>,
+    /// The number of events of the calendar in the last report
+    #[serde(default)]
+    pub last_event_count: Option<usize>,
+    /// The total duration of the events of the calendar in the last report, in seconds
+    #[serde(default)]
+    pub last_total_duration: Option<i64>,
+    /// A prefix or suffix of the titles of billable events, e.g. `[BILLABLE]`, for `report --billable-only`
+    #[serde(default)]
+    pub billable_marker: Option<String>,
+    /// The cost center of reports on this calendar, unless `report --cost-center` is given
+    #[serde(default)]
+    pub default_cost_center: Option<String>,
+    /// The `ETag` of the last download, sent as `If-None-Match` to only download the calendar if it changed
+    #[serde(default)]
+    pub etag: Option<String>,
+    /// The `Last-Modified` time of the last download, sent as `If-Modified-Since`
+    #[serde(default)]
+    pub last_modified: Option<String>,
+    /// The file the last download is stored in, used when the calendar didn't change
+    #[serde(default)]
+    pub cached_content_path: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum AuthMethod {
+    /// HTTP basic authentication
+    Basic { username: String, password: String },
+    /// A fixed bearer token
+    Bearer { token: String },
+    /// A bearer token obtained from an OAuth 2.0 token endpoint using a refresh token
+    OAuth2 {
+        client_id: String,
+        /// Required by some providers, like Google
+        #[serde(default)]
+        client_secret: Option<String>,
+        refresh_token: String,
+        token_endpoint: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedToken {
+    pub access_token: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl ICalConfig {
+    /// Compile the patterns of `normalizations`, keeping their order
+    pub fn normalization_regexes(&self) -> Result<Vec<(Regex, String)>> {
+        self.normalizations
+            .iter()
+            .map(|(pattern, replacement)| {
+                let regex = Regex::new(pattern).map_err(|e| {
+                    Error::msg(format!(
+                        "Invalid normalization pattern '{pattern}' of calendar {}: {e}",
+                        self.name
+                    ))
+                })?;
+                Ok((regex, replacement.clone()))
+            })
+            .collect()
+    }
+
+    /// The URL to request the calendar from: `resolved_url` if there is one, so the redirects aren't followed again.
+    /// The credentials of `url` aren't sent to another host though, so then `url` is requested instead
+    pub fn request_url(&self) -> &str {
+        let has_credentials =
+            self.auth.is_some() || matches!(self.source, IcsSource::CalDav { .. });
+        let host = |url: &str| {
+            Url::parse(url)
+                .ok()
+                .and_then(|url| url.host_str().map(str::to_string))
+        };
+
+        match &self.resolved_url {
+            Some(resolved_url) if !has_credentials || host(resolved_url) == host(&self.url) => {
+                resolved_url
+            }
+            Some(resolved_url) => {
+                debug!(
+                    "Not requesting {resolved_url} directly, it's on another host than {}",
+                    self.url
+                );
+                &self.url
+            }
+            None => &self.url,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum IcsSource {
+    /// A plain ICS feed, a local file or stdin
+    #[default]
+    Feed,
+    /// A CalDAV calendar collection, of which every ICS resource is fetched
+    CalDav { username: String, password: String },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthStatus {
+    /// Whether the URL responded with a successful status code
+    pub healthy: bool,
+    /// The HTTP status, or the error if no response was received
+    pub status: String,
+    pub response_time_ms: u128,
+    pub checked_at: DateTime<Utc>,
 }
 
 #[derive(Default, Debug, Serialize, Deserialize)]
 pub struct Config {
-    pub ical: Vec<ICalConfig>,
+    /// The calendars keyed by name, in the order they were added.
+    /// Stored as a list in the config file
+    #[serde(with = "ical_list")]
+    pub ical: IndexMap<String, ICalConfig>,
+    /// Regex patterns mapped to the canonical event title they should be replaced with
+    #[serde(default)]
+    pub normalize_titles: HashMap<String, String>,
+    /// Don't warn when the config file is stored inside a git repository
+    #[serde(default)]
+    pub no_git_warning: bool,
+    /// The User-Agent sent when downloading calendars. Defaults to `hour-calc/<version>`
+    #[serde(default)]
+    pub user_agent: Option<String>,
+    /// The day weeks start on
+    #[serde(default)]
+    pub week_start: StartOfWeek,
+    /// The colours and borders of the tables
+    #[serde(default)]
+    pub color_scheme: Option<ColorScheme>,
+    /// Draw table borders with ASCII characters only, like `report --ascii`
+    #[serde(default)]
+    pub use_ascii_table: bool,
+    /// The columns of the report table, like `report --columns`. Defaults to date, time, duration and summary
+    #[serde(default)]
+    pub visible_columns: Option<Vec<TableColumn>>,
+}
+
+/// (De)serialize the calendars as a list of [ICalConfig], keyed by their name
+mod ical_list {
+    use super::ICalConfig;
+    use indexmap::IndexMap;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        ical: &IndexMap<String, ICalConfig>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(ical.values())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<IndexMap<String, ICalConfig>, D::Error> {
+        let list = Vec::<ICalConfig>::deserialize(deserializer)?;
+        // Should duplicate names have ended up in the file, the last one wins
+        Ok(list
+            .into_iter()
+            .map(|ical_config| (ical_config.name.clone(), ical_config))
+            .collect())
+    }
 }
 
 impl Config {
@@ -42,19 +252,93 @@ impl Config {
                     fs::create_dir_all(&dest_dir).await?;
                 }
 
+                Ok(dest_dir.join("config.json"))
+            }
+        } else if #[cfg(unix)] {
+            // FreeBSD, OpenBSD, NetBSD, illumos etc. follow the XDG convention
+            async fn get_path() -> Result<PathBuf> {
+                let config_dir = match var("XDG_CONFIG_HOME") {
+                    Ok(config_dir) if !config_dir.is_empty() => PathBuf::from(config_dir),
+                    _ => PathBuf::from(var("HOME")?).join(".config"),
+                };
+                let dest_dir = config_dir
+                    .join("hour-calc");
+
+                if !dest_dir.exists() {
+                    fs::create_dir_all(&dest_dir).await?;
+                }
+
                 Ok(dest_dir.join("config.json"))
             }
         } else {
-            compiler_error!("Unsupported platform");
+            compile_error!("Unsupported platform");
         }
     }
 
+    /// Warn if the config file is stored inside a git repository,
+    /// as the calendar URLs it contains could accidentally be committed
+    #[instrument]
+    pub async fn warn_if_in_git_repo() {
+        let path = match Self::get_path().await {
+            Ok(path) => path,
+            Err(e) => {
+                debug!("Skipping the git repository check: {e}");
+                return;
+            }
+        };
+
+        let Some(repo) = path
+            .ancestors()
+            .skip(1)
+            .find(|dir| dir.join(".git").exists())
+        else {
+            return;
+        };
+
+        // Only the one flag is needed, so a config that fails to load doesn't stop the
+        // commands that could fix it
+        #[derive(Deserialize)]
+        struct GitWarning {
+            #[serde(default)]
+            no_git_warning: bool,
+        }
+
+        let no_git_warning = match fs::read(&path).await {
+            Ok(buf) => serde_json::from_slice::<GitWarning>(&buf)
+                .map(|config| config.no_git_warning)
+                .unwrap_or(false),
+            Err(_) => false,
+        };
+        if no_git_warning {
+            return;
+        }
+
+        let dir = path.parent().unwrap_or(repo);
+        eprintln!(
+            "Warning: the config file {} is inside the git repository {}. It contains your calendar URLs, which could accidentally be committed.",
+            path.display(),
+            repo.display()
+        );
+        eprintln!(
+            "Add {} to the .gitignore of the repository, or use --no-git-warning to hide this warning.",
+            dir.strip_prefix(repo).unwrap_or(dir).display()
+        );
+    }
+
+    #[instrument]
     pub async fn clear() -> Result<()> {
         let path = Self::get_path().await?;
         fs::remove_file(&path).await?;
+
+        let cache_dir = Self::cache_dir().await?;
+        if cache_dir.exists() {
+            fs::remove_dir_all(&cache_dir).await?;
+        }
+
         Ok(())
     }
 
+    #[instrument]
     pub async fn open() -> Result<Option<Self>> {
         let path = Self::get_path().await?;
         if !path.exists() {
@@ -65,16 +349,220 @@ impl Config {
         let mut buf = Vec::new();
         f.read_to_end(&mut buf).await?;
 
-        let this: Self = serde_json::from_slice(&buf)?;
-        Ok(Some(this))
+        Ok(Some(Self::parse(&buf)?))
     }
 
-    pub async fn store(&self) -> Result<()> {
+    fn parse(buf: &[u8]) -> Result<Self> {
+        let this: Self = serde_json::from_slice(buf)?;
+        this.validate()?;
+        Ok(this)
+    }
+
+    /// Check the parts of the config serde can't, so invalid configs fail on load
+    /// rather than halfway through a report
+    fn validate(&self) -> Result<()> {
+        for ical_config in self.ical.values() {
+            ical_config.normalization_regexes()?;
+        }
+
+        Ok(())
+    }
+
+    /// The directory downloads of calendars are cached in, next to the config file
+    async fn cache_dir() -> Result<PathBuf> {
         let path = Self::get_path().await?;
-        let mut f = fs::File::create(&path).await?;
+        Ok(path
+            .parent()
+            .ok_or(Error::msg("Config file has no parent directory"))?
+            .join("cache"))
+    }
+
+    /// The file the last download from `url` is stored in, in the `cache` directory.
+    /// Named after a hash of the URL, so different URLs never map to the same file
+    pub async fn cache_path(url: &str) -> Result<PathBuf> {
+        let dir = Self::cache_dir().await?;
+        if !dir.exists() {
+            fs::create_dir_all(&dir).await?;
+        }
+
+        Ok(dir.join(format!("{:016x}.ics", stable_hash(url))))
+    }
+
+    /// Delete a cached download, unless a calendar in the config still uses it.
+    /// A file which is already gone is fine, the cache is only used to skip unchanged downloads
+    pub async fn remove_cached_content(&self, path: &Path) -> Result<()> {
+        let in_use = self
+            .ical
+            .values()
+            .any(|ical_config| ical_config.cached_content_path.as_deref() == Some(path));
+        if in_use {
+            return Ok(());
+        }
+
+        Self::remove_cache_file(path).await
+    }
 
+    /// Delete a cached download, even if calendars still use it.
+    /// For when the download no longer matches the calendar, which then downloads it in full
+    pub async fn remove_cache_file(path: &Path) -> Result<()> {
+        match fs::remove_file(path).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Copy the config file to `config.json.bak.<timestamp>` next to it, returning the path of the copy
+    pub async fn backup() -> Result<PathBuf> {
+        let path = Self::get_path().await?;
+        let mut backup_path = path.clone().into_os_string();
+        backup_path.push(format!(".bak.{}", Utc::now().format("%Y%m%d%H%M%S")));
+        let backup_path = PathBuf::from(backup_path);
+
+        fs::copy(&path, &backup_path).await?;
+        Ok(backup_path)
+    }
+
+    /// Replace the config file with a backup made by [Config::backup], if the backup is a valid config
+    pub async fn restore(backup_path: &Path) -> Result<()> {
+        let buf = fs::read(backup_path).await?;
+        let backup: Self = serde_json::from_slice(&buf).map_err(|e| {
+            Error::msg(format!(
+                "{} is not a valid config: {e}",
+                backup_path.display()
+            ))
+        })?;
+        backup.validate()?;
+
+        fs::copy(backup_path, Self::get_path().await?).await?;
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    pub async fn store(&self) -> Result<()> {
+        let path = Self::get_path().await?;
         let buf = serde_json::to_vec_pretty(self)?;
-        f.write(&buf).await?;
+
+        // Write to a temporary file first, so a failed write can't leave a truncated config behind
+        let mut tmp_path = path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_path);
+        let mut f = fs::File::create(&tmp_path).await?;
+        f.write_all(&buf).await?;
+        f.sync_all().await?;
+        fs::rename(&tmp_path, &path).await?;
+
+        *LAST_STORED.lock().unwrap_or_else(|e| e.into_inner()) = Some(buf);
         Ok(())
     }
+
+    /// Call `callback` with the reloaded config whenever the config file is modified.
+    /// Changes within 500ms of each other result in a single reload.
+    /// Only returns if watching fails
+    #[instrument(skip_all)]
+    pub async fn watch(callback: impl Fn(Config)) -> Result<()> {
+        let path = Self::get_path().await?;
+        let dir = path
+            .parent()
+            .ok_or(Error::msg("Config file has no parent directory"))?;
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let watched_path = path.clone();
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+            if let Ok(event) = event {
+                if (event.kind.is_modify() || event.kind.is_create())
+                    && event.paths.contains(&watched_path)
+                {
+                    // The receiver only goes away when watching stops
+                    let _ = tx.send(());
+                }
+            }
+        })?;
+
+        // Watch the directory, as editors often replace the file instead of writing to it
+        watcher.watch(dir, RecursiveMode::NonRecursive)?;
+
+        while rx.recv().await.is_some() {
+            // Wait until no more changes come in
+            loop {
+                match timeout(WATCH_DEBOUNCE, rx.recv()).await {
+                    Ok(Some(())) => continue,
+                    Ok(None) => return Ok(()),
+                    Err(_) => break,
+                }
+            }
+
+            // Skip the changes this process made itself, e.g. the stats stored by a report
+            let buf = match fs::read(&path).await {
+                Ok(buf) => buf,
+                Err(e) => {
+                    warn!("Failed to reload config: {e}");
+                    continue;
+                }
+            };
+            let stored_by_self = LAST_STORED
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .as_ref()
+                .is_some_and(|stored| stored.eq(&buf));
+            if stored_by_self {
+                debug!("Ignoring the config written by this process");
+                continue;
+            }
+
+            match Self::parse(&buf) {
+                Ok(config) => callback(config),
+                // The file may be halfway through being written, the next change reloads it
+                Err(e) => warn!("Failed to reload config: {e}"),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The 64-bit FNV-1a hash of a string. Unlike the hasher of the standard library,
+/// it is the same across Rust releases, so cache file names stay valid
+fn stable_hash(value: &str) -> u64 {
+    value.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stable_hash_is_fnv1a() {
+        assert_eq!(stable_hash(""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(stable_hash("a"), 0xaf63_dc4c_8601_ec8c);
+        assert_ne!(
+            stable_hash("https://example.com/work client.ics"),
+            stable_hash("https://example.com/work_client.ics")
+        );
+    }
+
+    #[test]
+    fn request_url_keeps_credentials_on_their_host() {
+        let mut ical_config = ICalConfig {
+            url: "https://example.com/work.ics".to_string(),
+            resolved_url: Some("https://cdn.example.net/work.ics".to_string()),
+            ..ICalConfig::default()
+        };
+        assert_eq!(
+            ical_config.request_url(),
+            "https://cdn.example.net/work.ics"
+        );
+
+        ical_config.auth = Some(AuthMethod::Bearer {
+            token: "secret".to_string(),
+        });
+        assert_eq!(ical_config.request_url(), "https://example.com/work.ics");
+
+        ical_config.resolved_url = Some("https://example.com/calendars/work.ics".to_string());
+        assert_eq!(
+            ical_config.request_url(),
+            "https://example.com/calendars/work.ics"
+        );
+    }
 }
@@ -0,0 +1,171 @@
+use crate::config::ICalConfig;
+use crate::error::AppError;
+use crate::event::EventSummary;
+use crate::{calc_total_duration, fmt_duration};
+use encoding_rs::{UTF_8, WINDOWS_1252};
+use std::mem;
+
+/// Generate a CSV file with a row for every event, followed by the total and the cost center
+pub fn generate_csv(events: &[EventSummary], header: bool, cost_center: Option<&str>) -> String {
+    let mut buf = String::new();
+    if header {
+        push_row(&mut buf, &["Date", "Time", "Duration", "Title"]);
+    }
+
+    for event in events {
+        buf.push_str(&event.to_csv_row().join(","));
+        buf.push_str("\r\n");
+    }
+
+    push_row(
+        &mut buf,
+        &["Total", "", &fmt_duration(calc_total_duration(events)), ""],
+    );
+
+    if let Some(cost_center) = cost_center {
+        push_row(&mut buf, &["Cost center", cost_center, "", ""]);
+    }
+
+    buf
+}
+
+/// Read the events back from a CSV file generated by [generate_csv].
+/// The header, total and cost center rows are skipped
+pub fn parse_csv(input: &str) -> Result<Vec<EventSummary>, AppError> {
+    split_rows(input)
+        .into_iter()
+        .filter(|row| {
+            !matches!(
+                row.first().map(|field| unescape_field(field)).as_deref(),
+                Some("Date" | "Total" | "Cost center" | "")
+            )
+        })
+        .map(|row| EventSummary::from_csv_row(&row.iter().map(String::as_str).collect::<Vec<_>>()))
+        .collect()
+}
+
+/// Decode a CSV file written in any of the output encodings: UTF-8, with or without
+/// a byte order mark, or Latin-1. The byte order mark is removed
+pub fn decode_csv(bytes: &[u8]) -> String {
+    let (text, _, malformed) = UTF_8.decode(bytes);
+    if !malformed {
+        return text.trim_start_matches('\u{feff}').to_string();
+    }
+
+    // Latin-1 text with accents is rarely valid UTF-8
+    WINDOWS_1252
+        .decode_without_bom_handling(bytes)
+        .0
+        .into_owned()
+}
+
+/// Generate a CSV file with a row for every configured calendar
+pub fn generate_calendar_csv<'a>(calendars: impl Iterator<Item = &'a ICalConfig>) -> String {
+    let mut buf = String::new();
+    push_row(&mut buf, &["Index", "Name", "URL"]);
+
+    for (index, ical_config) in calendars.enumerate() {
+        push_row(
+            &mut buf,
+            &[&index.to_string(), &ical_config.name, &ical_config.url],
+        );
+    }
+
+    buf
+}
+
+fn push_row(buf: &mut String, fields: &[&str]) {
+    let row = fields
+        .iter()
+        .map(|field| escape_field(field))
+        .collect::<Vec<_>>()
+        .join(",");
+
+    buf.push_str(&row);
+    buf.push_str("\r\n");
+}
+
+/// Quote a field if it contains a character with a special meaning in CSV
+pub fn escape_field(field: &str) -> String {
+    if field.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Undo [escape_field], removing the quotes around a field
+pub fn unescape_field(field: &str) -> String {
+    match field
+        .strip_prefix('"')
+        .and_then(|field| field.strip_suffix('"'))
+    {
+        Some(quoted) => quoted.replace("\"\"", "\""),
+        None => field.to_string(),
+    }
+}
+
+/// Split CSV into rows of fields, which are kept as escaped by [escape_field].
+/// Separators and line breaks inside quoted fields are part of the field
+fn split_rows(input: &str) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+
+    for c in input.chars() {
+        match c {
+            // A doubled quote inside a quoted field toggles twice, so it stays quoted
+            '"' => {
+                quoted = !quoted;
+                field.push(c);
+            }
+            ',' if !quoted => row.push(mem::take(&mut field)),
+            '\r' if !quoted => {}
+            '\n' if !quoted => {
+                row.push(mem::take(&mut field));
+                rows.push(mem::take(&mut row));
+            }
+            _ => field.push(c),
+        }
+    }
+
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::test_event;
+
+    #[test]
+    fn parse_csv_reads_generated_csv() {
+        let events = [
+            (
+                "2024-03-01T09:00:00Z",
+                "2024-03-01T10:30:00Z",
+                "Standup, retro",
+            ),
+            (
+                "2024-03-02T13:00:00Z",
+                "2024-03-02T14:00:00Z",
+                "Line 1\nLine 2",
+            ),
+        ]
+        .map(|(start, end, title)| test_event(start, end, title));
+
+        let parsed = parse_csv(&generate_csv(&events, true, Some("CC-1"))).unwrap();
+
+        assert_eq!(parsed.len(), 2);
+        for (parsed, event) in parsed.iter().zip(&events) {
+            assert_eq!(parsed.title, event.title);
+            assert_eq!(parsed.start, event.start);
+            assert_eq!(parsed.duration_sec, event.duration_sec);
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use crate::event::EventError;
+use color_eyre::Report;
+use ical::parser::ParserError;
+use std::error::Error;
+use std::{fmt, io};
+
+/// Exit code for errors without a more specific code
+const EXIT_OTHER: i32 = 1;
+const EXIT_INVALID_ARGS: i32 = 2;
+const EXIT_NETWORK: i32 = 3;
+const EXIT_PARSE: i32 = 4;
+const EXIT_IO: i32 = 5;
+const EXIT_NO_EVENTS: i32 = 6;
+
+/// Errors which scripts may want to tell apart, each with its own exit code
+#[derive(Debug)]
+pub enum AppError {
+    /// The arguments refer to something which doesn't exist, or are missing a required value
+    InvalidArgs(String),
+    /// A calendar couldn't be downloaded
+    Network(String),
+    /// A calendar couldn't be parsed
+    Parse(String),
+    /// No events are left to report on
+    NoEvents,
+}
+
+impl AppError {
+    fn exit_code(&self) -> i32 {
+        match self {
+            Self::InvalidArgs(_) => EXIT_INVALID_ARGS,
+            Self::Network(_) => EXIT_NETWORK,
+            Self::Parse(_) => EXIT_PARSE,
+            Self::NoEvents => EXIT_NO_EVENTS,
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidArgs(e) | Self::Network(e) | Self::Parse(e) => write!(f, "{e}"),
+            Self::NoEvents => write!(f, "No events found"),
+        }
+    }
+}
+
+impl Error for AppError {}
+
+/// The exit code for an error, based on the first cause in its chain with a specific code
+pub fn exit_code(report: &Report) -> i32 {
+    for cause in report.chain() {
+        if let Some(e) = cause.downcast_ref::<AppError>() {
+            return e.exit_code();
+        } else if cause.is::<reqwest::Error>() {
+            return EXIT_NETWORK;
+        } else if cause.is::<EventError>() || cause.is::<ParserError>() {
+            return EXIT_PARSE;
+        } else if cause.is::<io::Error>() {
+            return EXIT_IO;
+        }
+    }
+
+    EXIT_OTHER
+}
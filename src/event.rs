@@ -0,0 +1,797 @@
+use crate::args::StartOfWeek;
+use crate::error::AppError;
+use crate::{csv, fmt_duration};
+use chrono::{
+    DateTime, Datelike, Duration, FixedOffset, IsoWeek, NaiveDate, NaiveDateTime, NaiveTime,
+    ParseError, TimeZone, Timelike, Utc,
+};
+use chrono_tz::Tz;
+use ical::parser::ical::component::IcalEvent;
+use ical::property::Property;
+use rrule::RRuleSet;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use tabled::Tabled;
+use tracing::warn;
+
+#[derive(Clone, Tabled, Serialize)]
+pub struct EventSummary {
+    #[tabled(rename = "Date")]
+    pub date: String,
+    #[tabled(rename = "Time")]
+    pub time: String,
+    #[tabled(rename = "Duration")]
+    pub duration: String,
+    #[tabled(rename = "Title")]
+    pub title: String,
+    #[tabled(rename = "Meeting?", display_with = "fmt_yes_no")]
+    pub is_meeting: bool,
+    /// The name of the calendar the event is from.
+    /// Empty until set by the caller, as the event itself doesn't know
+    #[tabled(rename = "Calendar")]
+    pub calendar: String,
+    #[tabled(skip)]
+    pub duration_sec: i64,
+    #[tabled(skip)]
+    pub start: DateTime<Utc>,
+    #[tabled(skip)]
+    pub end: DateTime<Utc>,
+    /// The offset `date` and `time` are displayed in, see [EventSummary::local_start]
+    #[tabled(skip)]
+    #[serde(skip)]
+    pub offset: FixedOffset,
+    /// The `LOCATION` of the event, empty if it has none
+    #[tabled(rename = "Location")]
+    #[serde(skip)]
+    pub location: String,
+    /// The `DESCRIPTION` of the event, empty if it has none
+    #[tabled(rename = "Description")]
+    #[serde(skip)]
+    pub description: String,
+    /// The `UID` of the event. Occurrences of a recurring event share the same UID
+    #[tabled(rename = "UID", display_with = "fmt_option")]
+    pub uid: Option<String>,
+    /// The values of all `CATEGORIES` properties of the event
+    #[tabled(skip)]
+    #[serde(skip)]
+    pub categories: Vec<String>,
+    /// Whether the event contains a `VALARM` component
+    #[tabled(skip)]
+    #[serde(skip)]
+    pub has_alarm: bool,
+}
+
+/// The category marking an event as billable, compared case-insensitively
+const BILLABLE_CATEGORY: &str = "Billable";
+
+impl EventSummary {
+    /// Whether the event has the `Billable` category, or a title starting or ending with `marker`
+    pub fn is_billable(&self, marker: Option<&str>) -> bool {
+        self.categories
+            .iter()
+            .any(|category| category.eq_ignore_ascii_case(BILLABLE_CATEGORY))
+            || marker.is_some_and(|marker| {
+                self.title.starts_with(marker) || self.title.ends_with(marker)
+            })
+    }
+
+    /// Whether the event is only a reminder: it has an alarm, but no duration
+    pub fn is_reminder(&self) -> bool {
+        self.has_alarm && self.start == self.end
+    }
+
+    /// The start of the event, in the offset it is displayed in
+    pub fn local_start(&self) -> DateTime<FixedOffset> {
+        self.start.with_timezone(&self.offset)
+    }
+
+    /// The end of the event, in the offset it is displayed in
+    pub fn local_end(&self) -> DateTime<FixedOffset> {
+        self.end.with_timezone(&self.offset)
+    }
+
+    /// The date the event starts on, in the offset it is displayed in
+    pub fn start_date(&self) -> NaiveDate {
+        self.local_start().date_naive()
+    }
+
+    /// The week the event starts in, see [week_of]
+    pub fn week(&self, week_start: StartOfWeek) -> IsoWeek {
+        week_of(self.start_date(), week_start)
+    }
+}
+
+/// The week a date is in. Numbered like ISO weeks, but starting on `week_start`
+pub fn week_of(date: NaiveDate, week_start: StartOfWeek) -> IsoWeek {
+    match week_start {
+        StartOfWeek::Monday => date.iso_week(),
+        // A Sunday belongs to the ISO week starting the next day
+        StartOfWeek::Sunday => (date + Duration::days(1)).iso_week(),
+    }
+}
+
+/// Reasons an ICS event could not be converted to an [EventSummary]
+#[derive(Debug)]
+pub enum EventError {
+    /// The event has no `DTSTART` property
+    MissingStart,
+    /// The event has no `DTEND` property
+    MissingEnd,
+    /// The `DTSTART`, `DTEND` or `EXDATE` property could not be parsed
+    InvalidDateTime(ParseError),
+    /// The `RRULE` property could not be parsed
+    InvalidRecurrence(String),
+}
+
+impl fmt::Display for EventError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingStart => write!(f, "Event is missing start property"),
+            Self::MissingEnd => write!(f, "Event is missing end property"),
+            Self::InvalidDateTime(e) => write!(f, "Event has an invalid date: {e}"),
+            Self::InvalidRecurrence(e) => write!(f, "Event has an invalid recurrence rule: {e}"),
+        }
+    }
+}
+
+impl Error for EventError {}
+
+impl From<ParseError> for EventError {
+    fn from(value: ParseError) -> Self {
+        Self::InvalidDateTime(value)
+    }
+}
+
+impl TryFrom<&IcalEvent> for EventSummary {
+    type Error = EventError;
+
+    fn try_from(event: &IcalEvent) -> Result<Self, Self::Error> {
+        let (start, end) = event_times(event)?;
+
+        // Get the title of the event, this one is optional
+        let title = property_value(event, "SUMMARY").unwrap_or_default();
+
+        // Events with multiple attendees are considered meetings
+        let attendees = event
+            .properties
+            .iter()
+            .filter(|prop| prop.name.eq("ATTENDEE"))
+            .count();
+
+        // A property may hold multiple, comma separated, categories
+        let categories = event
+            .properties
+            .iter()
+            .filter(|prop| prop.name.eq("CATEGORIES"))
+            .filter_map(|prop| prop.value.as_ref())
+            .flat_map(|value| value.split(','))
+            .map(|category| unescape_text(category.trim()))
+            .filter(|category| !category.is_empty())
+            .collect();
+
+        let description = property_value(event, "DESCRIPTION")
+            .map(|description| unescape_text(&description))
+            .unwrap_or_default();
+
+        Ok(Self {
+            location: property_value(event, "LOCATION")
+                .map(|location| unescape_text(&location))
+                .unwrap_or_default(),
+            description,
+            categories,
+            uid: property_value(event, "UID"),
+            has_alarm: !event.alarms.is_empty(),
+            ..Self::new(start, end, title, attendees > 1)
+        })
+    }
+}
+
+/// Undo the escaping of ICS text values, turning `\n` into a newline and `\,` into a comma
+fn unescape_text(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n' | 'N') => unescaped.push('\n'),
+            Some(escaped) => unescaped.push(escaped),
+            None => unescaped.push('\\'),
+        }
+    }
+
+    unescaped
+}
+
+/// Get the start and end of the event, in the offset they are specified in
+fn event_times(
+    event: &IcalEvent,
+) -> Result<(DateTime<FixedOffset>, DateTime<FixedOffset>), EventError> {
+    let dtstart = property_value(event, "DTSTART").ok_or(EventError::MissingStart)?;
+    let dtend = property_value(event, "DTEND").ok_or(EventError::MissingEnd)?;
+
+    Ok((
+        parse_ics_datetime(&dtstart, property_timezone(event, "DTSTART"))?,
+        parse_ics_datetime(&dtend, property_timezone(event, "DTEND"))?,
+    ))
+}
+
+impl EventSummary {
+    /// Create a summary of an event from its start and end.
+    /// The date and time are displayed in the offset they are specified in
+    pub fn new(
+        start: DateTime<FixedOffset>,
+        end: DateTime<FixedOffset>,
+        title: String,
+        is_meeting: bool,
+    ) -> Self {
+        // An event ending at midnight doesn't extend into the next day
+        let last_day = if end > start && end.num_seconds_from_midnight() == 0 {
+            end.date_naive() - Duration::days(1)
+        } else {
+            end.date_naive()
+        };
+        let days_spanned = (last_day - start.date_naive()).num_days();
+
+        // Format the event date as DD-MM-YYYY - DD-MM-YYYY
+        // Account for if the date spans multiple days
+        let date = if days_spanned == 0 {
+            format!("{:02}-{:02}-{}", start.day(), start.month(), start.year())
+        } else {
+            format!(
+                "{:02}-{:02}-{} - {:02}-{:02}-{}",
+                start.day(),
+                start.month(),
+                start.year(),
+                last_day.day(),
+                last_day.month(),
+                last_day.year()
+            )
+        };
+
+        // Format the event timespan as HH:MM - HH:MM, marking the number of days
+        // the event ends after it starts. An overnight event is marked with (+1),
+        // which distinguishes it from blocks spanning multiple days
+        let mut time = format!(
+            "{:02}:{:02} - {:02}:{:02}",
+            start.hour(),
+            start.minute(),
+            end.hour(),
+            end.minute()
+        );
+        if days_spanned > 0 {
+            time.push_str(&format!(" (+{days_spanned})"));
+        }
+
+        let duration = end - start;
+        Self {
+            date,
+            time,
+            duration: fmt_duration(duration.num_seconds()),
+            title,
+            is_meeting,
+            duration_sec: duration.num_seconds(),
+            start: start.with_timezone(&Utc),
+            end: end.with_timezone(&Utc),
+            offset: *start.offset(),
+            calendar: String::new(),
+            location: String::new(),
+            description: String::new(),
+            categories: Vec::new(),
+            uid: None,
+            has_alarm: false,
+        }
+    }
+
+    /// The date, time, duration and title of the event as fields of a CSV row,
+    /// quoted where needed
+    pub fn to_csv_row(&self) -> Vec<String> {
+        [&self.date, &self.time, &self.duration, &self.title]
+            .into_iter()
+            .map(|field| csv::escape_field(field))
+            .collect()
+    }
+
+    /// Read an event back from the fields of a row written by [EventSummary::to_csv_row].
+    /// As the row doesn't contain the offset of the event, its times are taken to be in UTC
+    pub fn from_csv_row(row: &[&str]) -> Result<Self, AppError> {
+        let [date, time, duration, title] = row else {
+            return Err(AppError::Parse(format!(
+                "Expected 4 fields in CSV row, got {}",
+                row.len()
+            )));
+        };
+        let [date, time, duration, title] =
+            [date, time, duration, title].map(|field| csv::unescape_field(field));
+
+        // Only the start of multi-day events is needed, the end follows from the duration
+        let start_date = date.split(" - ").next().unwrap_or(&date);
+        let start_date = NaiveDate::parse_from_str(start_date, "%d-%m-%Y")
+            .map_err(|e| AppError::Parse(format!("Invalid date '{date}' in CSV row: {e}")))?;
+        let start_time = time.split(" - ").next().unwrap_or(&time);
+        let start_time = NaiveTime::parse_from_str(start_time, "%H:%M")
+            .map_err(|e| AppError::Parse(format!("Invalid time '{time}' in CSV row: {e}")))?;
+        let duration_sec = parse_duration(&duration)
+            .ok_or_else(|| AppError::Parse(format!("Invalid duration '{duration}' in CSV row")))?;
+
+        let start = start_date.and_time(start_time).and_utc().fixed_offset();
+        Ok(Self::new(
+            start,
+            start + Duration::seconds(duration_sec),
+            title,
+            false,
+        ))
+    }
+
+    /// The same event, with its date and time displayed in `timezone`.
+    /// The start and end stay the same, only the offset they are displayed in changes
+    pub fn in_timezone(&self, timezone: Tz) -> Self {
+        let converted = Self::new(
+            self.start.with_timezone(&timezone).fixed_offset(),
+            self.end.with_timezone(&timezone).fixed_offset(),
+            self.title.clone(),
+            self.is_meeting,
+        );
+
+        Self {
+            date: converted.date,
+            time: converted.time,
+            offset: converted.offset,
+            ..self.clone()
+        }
+    }
+}
+
+/// Parse a duration formatted as HH:MM:SS by [fmt_duration] back to seconds.
+/// Negative durations are prefixed with `-`
+fn parse_duration(duration: &str) -> Option<i64> {
+    let (sign, magnitude) = match duration.strip_prefix('-') {
+        Some(magnitude) => (-1, magnitude),
+        None => (1, duration),
+    };
+
+    let mut parts = magnitude.split(':').map(|part| part.parse::<u32>().ok());
+    let (hours, minutes, seconds) = (parts.next()??, parts.next()??, parts.next()??);
+    if parts.next().is_some() || minutes >= 60 || seconds >= 60 {
+        return None;
+    }
+
+    Some(sign * (i64::from(hours) * 3600 + i64::from(minutes) * 60 + i64::from(seconds)))
+}
+
+/// Maximum number of occurrences a recurring event is expanded into.
+/// Recurrences without an end would otherwise never stop, [occurrences] warns when this is reached
+const MAX_OCCURRENCES: u16 = 5000;
+
+/// A date on which a recurring event does not occur, from an `EXDATE` property
+/// or the `RECURRENCE-ID` of a modified instance
+#[derive(Clone)]
+pub enum ExcludedDate {
+    /// Excludes the occurrence on this day
+    Date(NaiveDate),
+    /// Excludes the occurrence starting at exactly this time
+    DateTime(DateTime<Utc>),
+}
+
+impl ExcludedDate {
+    fn parse(value: &str, timezone: Option<Tz>) -> Result<Self, EventError> {
+        if value.contains('T') {
+            let datetime = parse_ics_datetime(value, timezone)?;
+            Ok(Self::DateTime(datetime.with_timezone(&Utc)))
+        } else {
+            Ok(Self::Date(NaiveDate::parse_from_str(value, "%Y%m%d")?))
+        }
+    }
+
+    fn matches(&self, occurrence: &DateTime<FixedOffset>) -> bool {
+        match self {
+            Self::Date(date) => occurrence.date_naive().eq(date),
+            Self::DateTime(datetime) => occurrence.with_timezone(&Utc).eq(datetime),
+        }
+    }
+}
+
+/// The occurrences of recurring events which are replaced by a modified instance, keyed by UID.
+/// A modified instance is a separate event with the same UID, and a `RECURRENCE-ID`
+/// holding the start of the occurrence it replaces
+pub fn overridden_occurrences(events: &[IcalEvent]) -> HashMap<String, Vec<ExcludedDate>> {
+    let mut overridden: HashMap<String, Vec<ExcludedDate>> = HashMap::new();
+    for event in events {
+        let (Some(uid), Some(recurrence_id)) = (
+            property_value(event, "UID"),
+            property_value(event, "RECURRENCE-ID"),
+        ) else {
+            continue;
+        };
+
+        match ExcludedDate::parse(
+            recurrence_id.trim(),
+            property_timezone(event, "RECURRENCE-ID"),
+        ) {
+            Ok(date) => overridden.entry(uid).or_default().push(date),
+            Err(e) => warn!("Invalid RECURRENCE-ID '{recurrence_id}', ignoring it: {e}"),
+        }
+    }
+
+    overridden
+}
+
+/// Convert an ICS event to a summary of every one of its occurrences.
+/// Events with an `RRULE` are expanded up to `until`, skipping the dates listed in `EXDATE`
+/// and the occurrences replaced by a modified instance, see [overridden_occurrences].
+/// When `DTSTART` has a `TZID`, the recurrence is expanded in that timezone,
+/// so occurrences keep their local time across DST changes
+pub fn occurrences(
+    event: &IcalEvent,
+    overridden: &HashMap<String, Vec<ExcludedDate>>,
+    until: DateTime<Utc>,
+) -> Result<Vec<EventSummary>, EventError> {
+    let first = EventSummary::try_from(event)?;
+    let rrule = match property_value(event, "RRULE") {
+        Some(rrule) => rrule,
+        None => return Ok(vec![first]),
+    };
+
+    let timezone = property_timezone(event, "DTSTART");
+    let dtstart = match timezone {
+        Some(timezone) => format!(
+            "DTSTART;TZID={timezone}:{}",
+            first.start.with_timezone(&timezone).format("%Y%m%dT%H%M%S")
+        ),
+        None => format!(
+            "DTSTART:{}",
+            first.start.with_timezone(&Utc).format("%Y%m%dT%H%M%SZ")
+        ),
+    };
+    let rrule_set = format!("{dtstart}\nRRULE:{rrule}")
+        .parse::<RRuleSet>()
+        .map_err(|e| EventError::InvalidRecurrence(e.to_string()))?
+        .before(until.with_timezone(&rrule::Tz::UTC));
+
+    let mut excluded = excluded_dates(event)?;
+    if let Some(overridden) = first.uid.as_ref().and_then(|uid| overridden.get(uid)) {
+        excluded.extend(overridden.iter().cloned());
+    }
+    let duration = first.end - first.start;
+    // Without a timezone, occurrences are displayed in the same offset as the first one
+    let offset = first.offset;
+
+    let result = rrule_set.all(MAX_OCCURRENCES);
+    if result.limited {
+        warn!(
+            "Recurring event '{}' (UID {}) has more than {MAX_OCCURRENCES} occurrences, only the first ones are included",
+            first.title,
+            first.uid.as_deref().unwrap_or("unknown")
+        );
+    }
+
+    let occurrences = result
+        .dates
+        .into_iter()
+        .map(|occurrence| match timezone {
+            Some(_) => occurrence.fixed_offset(),
+            None => occurrence.with_timezone(&offset),
+        })
+        .filter(|occurrence| !excluded.iter().any(|excluded| excluded.matches(occurrence)))
+        .map(|start| EventSummary {
+            location: first.location.clone(),
+            description: first.description.clone(),
+            categories: first.categories.clone(),
+            uid: first.uid.clone(),
+            has_alarm: first.has_alarm,
+            ..EventSummary::new(
+                start,
+                start + duration,
+                first.title.clone(),
+                first.is_meeting,
+            )
+        })
+        .collect();
+
+    Ok(occurrences)
+}
+
+/// Parse all `EXDATE` properties of the event.
+/// A single property may contain multiple, comma separated, dates
+fn excluded_dates(event: &IcalEvent) -> Result<Vec<ExcludedDate>, EventError> {
+    event
+        .properties
+        .iter()
+        .filter(|prop| prop.name.eq("EXDATE"))
+        .flat_map(|prop| {
+            let timezone = timezone_param(prop);
+            prop.value
+                .iter()
+                .flat_map(|value| value.split(','))
+                .map(move |value| ExcludedDate::parse(value.trim(), timezone))
+        })
+        .collect()
+}
+
+/// Get the timezone of the first property with the given name, see [timezone_param]
+fn property_timezone(event: &IcalEvent, name: &str) -> Option<Tz> {
+    event
+        .properties
+        .iter()
+        .find(|prop| prop.name.eq(name))
+        .and_then(timezone_param)
+}
+
+/// The timezone given by the `TZID` parameter of a property.
+/// Only IANA names like `Europe/Amsterdam` are known, other timezones are ignored
+fn timezone_param(prop: &Property) -> Option<Tz> {
+    prop.params
+        .as_ref()?
+        .iter()
+        .find(|(name, _)| name.eq("TZID"))
+        .and_then(|(_, values)| values.first())
+        .and_then(|tzid| tzid.trim_matches('"').parse().ok())
+}
+
+/// Get the value of the first property with the given name, if it has a value
+pub fn property_value(event: &IcalEvent, name: &str) -> Option<String> {
+    event
+        .properties
+        .iter()
+        .find(|prop| prop.name.eq(name))
+        .and_then(|prop| prop.value.clone())
+}
+
+fn fmt_yes_no(value: &bool) -> String {
+    let text = if *value { "Yes" } else { "No" };
+    text.to_string()
+}
+
+fn fmt_option(value: &Option<String>) -> String {
+    value.clone().unwrap_or_default()
+}
+
+/// Parse a date or datetime in the basic ISO 8601 format used by ICS files.
+/// Accepts dates (`20220921`, taken as midnight UTC) and datetimes with optional fractional
+/// seconds, either in UTC (`20220921T151530Z`), with an offset (`20220921T151530+0200`)
+/// or local (`20220921T151530`, taken to be in `timezone`, or UTC without one)
+fn parse_ics_datetime(
+    input: &str,
+    timezone: Option<Tz>,
+) -> Result<DateTime<FixedOffset>, ParseError> {
+    const DATETIME_FORMAT: &str = "%Y%m%dT%H%M%S%.f";
+
+    let Some((_, time)) = input.split_once('T') else {
+        let date = NaiveDate::parse_from_str(input, "%Y%m%d")?;
+        return Ok(date.and_time(NaiveTime::MIN).and_utc().fixed_offset());
+    };
+
+    if let Some(datetime) = input.strip_suffix('Z') {
+        Ok(NaiveDateTime::parse_from_str(datetime, DATETIME_FORMAT)?
+            .and_utc()
+            .fixed_offset())
+    } else if time.contains(['+', '-']) {
+        DateTime::parse_from_str(input, &format!("{DATETIME_FORMAT}%z"))
+    } else {
+        let datetime = NaiveDateTime::parse_from_str(input, DATETIME_FORMAT)?;
+        // Times skipped by a DST change don't exist in the timezone, so fall back to UTC for those
+        let local = timezone.and_then(|timezone| {
+            timezone
+                .from_local_datetime(&datetime)
+                .earliest()
+                .map(|local| local.fixed_offset())
+        });
+        Ok(local.unwrap_or_else(|| datetime.and_utc().fixed_offset()))
+    }
+}
+
+/// An event between two RFC 3339 datetimes, for the tests of every module
+#[cfg(test)]
+pub(crate) fn test_event(start: &str, end: &str, title: &str) -> EventSummary {
+    EventSummary::new(
+        DateTime::parse_from_rfc3339(start).unwrap(),
+        DateTime::parse_from_rfc3339(end).unwrap(),
+        title.to_string(),
+        false,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(event: &EventSummary) -> EventSummary {
+        let row = event.to_csv_row();
+        EventSummary::from_csv_row(&row.iter().map(String::as_str).collect::<Vec<_>>()).unwrap()
+    }
+
+    #[test]
+    fn csv_row_round_trips_quoted_title() {
+        let event = test_event(
+            "2024-03-01T09:15:00Z",
+            "2024-03-01T10:45:00Z",
+            "Review \"Q1\", part 2",
+        );
+        let parsed = round_trip(&event);
+
+        assert_eq!(parsed.title, event.title);
+        assert_eq!(parsed.start, event.start);
+        assert_eq!(parsed.duration_sec, 90 * 60);
+        assert_eq!(parsed.to_csv_row(), event.to_csv_row());
+    }
+
+    #[test]
+    fn csv_row_round_trips_multi_day_event() {
+        let event = test_event("2024-03-01T22:00:00Z", "2024-03-03T02:30:00Z", "Deploy");
+        let parsed = round_trip(&event);
+
+        assert_eq!(parsed.end, event.end);
+        assert_eq!(parsed.to_csv_row(), event.to_csv_row());
+    }
+
+    #[test]
+    fn csv_row_round_trips_negative_duration() {
+        let event = EventSummary {
+            duration_sec: -90,
+            duration: fmt_duration(-90),
+            ..test_event("2024-03-01T09:00:00Z", "2024-03-01T09:00:00Z", "Correction")
+        };
+        let row = event.to_csv_row();
+        let parsed =
+            EventSummary::from_csv_row(&row.iter().map(String::as_str).collect::<Vec<_>>())
+                .unwrap();
+
+        assert_eq!(parsed.duration_sec, -90);
+        assert_eq!(parsed.duration, "-00:01:30");
+    }
+
+    #[test]
+    fn csv_row_rejects_wrong_field_count() {
+        assert!(EventSummary::from_csv_row(&["01-03-2024", "09:00 - 10:00"]).is_err());
+    }
+
+    /// Parse the events of a calendar, given the lines between `BEGIN:VCALENDAR` and `END:VCALENDAR`
+    fn ical_events(lines: &str) -> Vec<IcalEvent> {
+        let ics = format!("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n{lines}END:VCALENDAR\r\n");
+        ical::IcalParser::new(ics.as_bytes())
+            .next()
+            .unwrap()
+            .unwrap()
+            .events
+    }
+
+    fn expand(events: &[IcalEvent]) -> Vec<EventSummary> {
+        let until = DateTime::parse_from_rfc3339("2024-12-31T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        occurrences(&events[0], &overridden_occurrences(events), until).unwrap()
+    }
+
+    #[test]
+    fn occurrences_skip_excluded_dates() {
+        let events = ical_events(
+            "BEGIN:VEVENT\r\n\
+             UID:standup\r\n\
+             DTSTART:20240304T090000Z\r\n\
+             DTEND:20240304T091500Z\r\n\
+             RRULE:FREQ=DAILY;COUNT=4\r\n\
+             EXDATE:20240305T090000Z,20240306T090000Z\r\n\
+             END:VEVENT\r\n",
+        );
+        let dates = expand(&events)
+            .into_iter()
+            .map(|event| event.date)
+            .collect::<Vec<_>>();
+
+        assert_eq!(dates, ["04-03-2024", "07-03-2024"]);
+    }
+
+    #[test]
+    fn occurrences_skip_overridden_instances() {
+        let events = ical_events(
+            "BEGIN:VEVENT\r\n\
+             UID:standup\r\n\
+             DTSTART:20240304T090000Z\r\n\
+             DTEND:20240304T091500Z\r\n\
+             RRULE:FREQ=DAILY;COUNT=3\r\n\
+             END:VEVENT\r\n\
+             BEGIN:VEVENT\r\n\
+             UID:standup\r\n\
+             RECURRENCE-ID:20240305T090000Z\r\n\
+             DTSTART:20240305T140000Z\r\n\
+             DTEND:20240305T141500Z\r\n\
+             END:VEVENT\r\n",
+        );
+        let dates = expand(&events)
+            .into_iter()
+            .map(|event| event.date)
+            .collect::<Vec<_>>();
+
+        // The modified instance is an event of its own
+        assert_eq!(dates, ["04-03-2024", "06-03-2024"]);
+    }
+
+    #[test]
+    fn occurrences_keep_local_time_across_dst() {
+        let events = ical_events(
+            "BEGIN:VEVENT\r\n\
+             UID:planning\r\n\
+             DTSTART;TZID=Europe/Amsterdam:20240329T090000\r\n\
+             DTEND;TZID=Europe/Amsterdam:20240329T100000\r\n\
+             RRULE:FREQ=WEEKLY;COUNT=2\r\n\
+             END:VEVENT\r\n",
+        );
+        let occurrences = expand(&events);
+
+        assert_eq!(occurrences.len(), 2);
+        assert_eq!(
+            occurrences[0].local_start().to_rfc3339(),
+            "2024-03-29T09:00:00+01:00"
+        );
+        assert_eq!(
+            occurrences[1].local_start().to_rfc3339(),
+            "2024-04-05T09:00:00+02:00"
+        );
+        assert_eq!(occurrences[1].duration_sec, 3600);
+    }
+
+    #[test]
+    fn parse_ics_datetime_reads_date_as_midnight_utc() {
+        let parsed = parse_ics_datetime("20240301", None).unwrap();
+        assert_eq!(parsed.to_rfc3339(), "2024-03-01T00:00:00+00:00");
+    }
+
+    #[test]
+    fn parse_ics_datetime_reads_utc() {
+        let amsterdam = "Europe/Amsterdam".parse().ok();
+        // The timezone only applies to local times
+        let parsed = parse_ics_datetime("20240301T091530Z", amsterdam).unwrap();
+        assert_eq!(parsed.to_rfc3339(), "2024-03-01T09:15:30+00:00");
+    }
+
+    #[test]
+    fn parse_ics_datetime_reads_offset() {
+        let parsed = parse_ics_datetime("20240301T091530-0500", None).unwrap();
+        assert_eq!(parsed.to_rfc3339(), "2024-03-01T09:15:30-05:00");
+    }
+
+    #[test]
+    fn parse_ics_datetime_reads_local_time_in_timezone() {
+        let amsterdam = "Europe/Amsterdam".parse().ok();
+        let parsed = parse_ics_datetime("20240701T091530", amsterdam).unwrap();
+        assert_eq!(parsed.to_rfc3339(), "2024-07-01T09:15:30+02:00");
+    }
+
+    #[test]
+    fn unknown_timezone_is_taken_as_utc() {
+        let events = ical_events(
+            "BEGIN:VEVENT\r\n\
+             DTSTART;TZID=Mars/Olympus_Mons:20240301T090000\r\n\
+             DTEND;TZID=Mars/Olympus_Mons:20240301T100000\r\n\
+             END:VEVENT\r\n",
+        );
+        let event = EventSummary::try_from(&events[0]).unwrap();
+
+        assert_eq!(
+            event.local_start().to_rfc3339(),
+            "2024-03-01T09:00:00+00:00"
+        );
+        assert_eq!(event.duration_sec, 3600);
+    }
+
+    #[test]
+    fn event_without_end_is_rejected() {
+        // DURATION isn't supported, so an event needs a DTEND
+        let events = ical_events(
+            "BEGIN:VEVENT\r\n\
+             DTSTART:20240301T090000Z\r\n\
+             DURATION:PT1H\r\n\
+             END:VEVENT\r\n",
+        );
+
+        assert!(matches!(
+            EventSummary::try_from(&events[0]),
+            Err(EventError::MissingEnd)
+        ));
+    }
+}
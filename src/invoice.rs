@@ -0,0 +1,171 @@
+use crate::calc_total_duration;
+use crate::event::EventSummary;
+use crate::pdf::{self, PdfOptions, PdfRow};
+use chrono::NaiveDate;
+use color_eyre::Result;
+
+/// Everything shown on an invoice besides the events
+#[derive(Debug)]
+pub struct InvoiceData {
+    pub client_name: String,
+    pub invoice_number: String,
+    pub invoice_date: NaiveDate,
+    pub due_date: NaiveDate,
+    /// Price per hour, excluding VAT
+    pub rate: f64,
+    /// VAT percentage, e.g. `21.0`
+    pub vat_rate: f64,
+}
+
+impl InvoiceData {
+    /// Price of a single event, excluding VAT, rounded to cents like it's shown on its line
+    pub fn line_amount(&self, event: &EventSummary) -> f64 {
+        round_to_cents(hours(event.duration_sec) * self.rate)
+    }
+
+    /// Price of all events, excluding VAT.
+    /// The sum of the lines, so the subtotal adds up to what's on the invoice
+    pub fn subtotal(&self, events: &[EventSummary]) -> f64 {
+        round_to_cents(events.iter().map(|event| self.line_amount(event)).sum())
+    }
+
+    pub fn tax_amount(&self, events: &[EventSummary]) -> f64 {
+        round_to_cents(self.subtotal(events) * self.vat_rate / 100.0)
+    }
+
+    /// Price of all events, including VAT
+    pub fn total(&self, events: &[EventSummary]) -> f64 {
+        self.subtotal(events) + self.tax_amount(events)
+    }
+}
+
+/// Generate an invoice with a line for every event, followed by the subtotal, VAT and total
+pub async fn generate_invoice_pdf(
+    data: &InvoiceData,
+    events: &[EventSummary],
+    options: &PdfOptions,
+) -> Result<Vec<u8>> {
+    let locale = options.locale;
+
+    let header = vec![
+        row(&[locale.invoice_title, ""], true),
+        row(&[locale.client, &data.client_name], false),
+        row(&[locale.invoice_number, &data.invoice_number], false),
+        row(&[locale.invoice_date, &fmt_date(data.invoice_date)], false),
+        row(&[locale.due_date, &fmt_date(data.due_date)], false),
+        row(&[locale.rate, &fmt_amount(data.rate)], false),
+        PdfRow::Empty,
+    ];
+
+    let mut lines = vec![row(
+        &[locale.date, locale.description, locale.hours, locale.amount],
+        true,
+    )];
+    lines.extend(events.iter().map(|event| {
+        row(
+            &[
+                &event.date,
+                &event.title,
+                &fmt_hours(event.duration_sec),
+                &fmt_amount(data.line_amount(event)),
+            ],
+            false,
+        )
+    }));
+
+    lines.push(PdfRow::Empty);
+    lines.push(row(
+        &[
+            "",
+            locale.subtotal,
+            &fmt_hours(calc_total_duration(events)),
+            &fmt_amount(data.subtotal(events)),
+        ],
+        false,
+    ));
+    lines.push(row(
+        &[
+            "",
+            &format!("{} {}%", locale.vat, data.vat_rate),
+            "",
+            &fmt_amount(data.tax_amount(events)),
+        ],
+        false,
+    ));
+    lines.push(row(
+        &["", locale.total_due, "", &fmt_amount(data.total(events))],
+        true,
+    ));
+
+    pdf::render_tables(&[header, lines], options).await
+}
+
+fn row(cells: &[&str], bold: bool) -> PdfRow {
+    PdfRow::Cells {
+        cells: cells.iter().map(|cell| cell.to_string()).collect(),
+        bold,
+    }
+}
+
+fn hours(secs: i64) -> f64 {
+    secs as f64 / 3600.0
+}
+
+fn round_to_cents(amount: f64) -> f64 {
+    (amount * 100.0).round() / 100.0
+}
+
+fn fmt_hours(secs: i64) -> String {
+    format!("{:.2}", hours(secs))
+}
+
+fn fmt_amount(amount: f64) -> String {
+    format!("{amount:.2}")
+}
+
+fn fmt_date(date: NaiveDate) -> String {
+    date.format("%d-%m-%Y").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::test_event;
+
+    #[test]
+    fn fmt_hours_rounds_to_hundredths() {
+        assert_eq!(fmt_hours(0), "0.00");
+        assert_eq!(fmt_hours(20 * 60), "0.33");
+        assert_eq!(fmt_hours(40 * 60), "0.67");
+        assert_eq!(fmt_hours(-90 * 60), "-1.50");
+        assert_eq!(fmt_hours(30 * 3600 + 36), "30.01");
+    }
+
+    #[test]
+    fn subtotal_is_sum_of_rounded_lines() {
+        let data = InvoiceData {
+            client_name: "Client".to_string(),
+            invoice_number: "2024-001".to_string(),
+            invoice_date: NaiveDate::from_ymd_opt(2024, 4, 1).unwrap(),
+            due_date: NaiveDate::from_ymd_opt(2024, 5, 1).unwrap(),
+            rate: 100.0,
+            vat_rate: 21.0,
+        };
+        // 1.333 hours each, of which the unrounded sum would be 400.00
+        let events = [("09:00", "10:20"), ("11:00", "12:20"), ("13:00", "14:20")]
+            .iter()
+            .map(|(start, end)| {
+                test_event(
+                    &format!("2024-03-01T{start}:00Z"),
+                    &format!("2024-03-01T{end}:00Z"),
+                    "Work",
+                )
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(fmt_amount(data.line_amount(&events[0])), "133.33");
+        assert_eq!(fmt_amount(data.subtotal(&events)), "399.99");
+        assert_eq!(fmt_amount(data.tax_amount(&events)), "84.00");
+        assert_eq!(fmt_amount(data.total(&events)), "483.99");
+    }
+}
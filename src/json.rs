@@ -0,0 +1,46 @@
+use crate::event::EventSummary;
+use crate::{calc_total_duration, fmt_duration};
+use color_eyre::Result;
+use serde::Serialize;
+
+/// A line of newline-delimited JSON output
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Line<'a> {
+    Event(&'a EventSummary),
+    Summary {
+        event_count: usize,
+        total_duration: String,
+        total_duration_sec: i64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        cost_center: Option<&'a str>,
+    },
+}
+
+/// Generate newline-delimited JSON with an object for every event, followed by a summary object
+pub fn generate_ndjson(events: &[EventSummary], cost_center: Option<&str>) -> Result<String> {
+    let mut buf = String::new();
+    for event in events {
+        push_line(&mut buf, &Line::Event(event))?;
+    }
+
+    let total = calc_total_duration(events);
+    push_line(
+        &mut buf,
+        &Line::Summary {
+            event_count: events.len(),
+            total_duration: fmt_duration(total),
+            total_duration_sec: total,
+            cost_center,
+        },
+    )?;
+
+    Ok(buf)
+}
+
+fn push_line(buf: &mut String, line: &Line) -> Result<()> {
+    // Compact serialization never contains newlines, so every object stays on a single line
+    buf.push_str(&serde_json::to_string(line)?);
+    buf.push('\n');
+    Ok(())
+}
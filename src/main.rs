@@ -1,74 +1,302 @@
-use crate::args::{Args, Commands, ConfigureCommands, IcsCommands, OutFormat};
-use crate::config::{Config, ICalConfig};
-use chrono::{DateTime, Datelike, Timelike};
-use clap::Parser;
+use crate::args::{
+    Args, ColorScheme, Commands, ConfigureCommands, GroupBy, IcsCommands, IcsSortBy, InvoiceArgs,
+    ListFormat, OutFormat, OutputEncoding, ReportArgs, SortBy, StartOfWeek, TableColumn,
+};
+use crate::config::{AuthMethod, CachedToken, Config, HealthStatus, ICalConfig, IcsSource};
+use crate::error::AppError;
+use crate::event::{EventError, EventSummary};
+use crate::invoice::InvoiceData;
+use crate::pdf::{InvoiceDetails, PdfLocale, PdfOptions};
+use chrono::{
+    DateTime, Datelike, Duration, Local, Month, Months, NaiveDate, NaiveTime, Timelike, Utc,
+    Weekday,
+};
+use chrono_tz::Tz;
+use clap::{Parser, ValueEnum};
 use color_eyre::eyre::{Error, Result};
+use dialoguer::{Confirm, Input, Select};
+use encoding_rs::{Encoding, UTF_8, WINDOWS_1252};
+use futures::future::try_join_all;
 use ical::IcalParser;
-use reqwest::Client;
-use std::io::{BufReader, Cursor};
-use tabled::{Panel, Style, Table, Tabled};
-use tracing::warn;
+use regex::Regex;
+use reqwest::header::{
+    HeaderName, CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+};
+use reqwest::{redirect, Client, Method, RequestBuilder, StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::env;
+use std::io::{BufReader, Cursor, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::Instant;
+use tabled::color::Color;
+use tabled::object::{Columns, Rows};
+use tabled::{Disable, Modify, Panel, Style, Table, Tabled, Width};
+use tokio::fs;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::time::timeout;
+use tracing::{debug, info, instrument, warn};
 
 mod args;
 mod config;
+mod csv;
+mod error;
+mod event;
+mod invoice;
+mod json;
+mod markdown;
+mod org;
 mod pdf;
-
-#[derive(Tabled)]
-pub struct EventSummary {
-    #[tabled(rename = "Date")]
-    date: String,
-    #[tabled(rename = "Time")]
-    time: String,
-    #[tabled(rename = "Duration")]
-    duration: String,
-    #[tabled(skip)]
-    date_start: u32,
-    #[tabled(skip)]
-    month_start: u32,
-    #[tabled(skip)]
-    year_start: i32,
-    #[tabled(skip)]
-    duration_sec: i64,
-}
+mod template;
+mod timew;
 
 #[tokio::main]
 async fn main() -> Result<()> {
     color_eyre::install()?;
 
+    if let Err(e) = run().await {
+        eprintln!("Error: {e:?}");
+        process::exit(error::exit_code(&e));
+    }
+
+    Ok(())
+}
+
+async fn run() -> Result<()> {
     let args = Args::parse();
 
+    if !args.no_git_warning {
+        Config::warn_if_in_git_repo().await;
+    }
+
     match args.commands {
         Commands::Configure { configure_commands } => match configure_commands {
             ConfigureCommands::Ics { ics_commands } => {
                 let mut config = Config::open().await?.unwrap_or_default();
                 match ics_commands {
-                    IcsCommands::List => ics_list(&mut config).await?,
-                    IcsCommands::Add { name, link } => ics_add(&mut config, name, link).await?,
+                    IcsCommands::List { format, verbose } => {
+                        ics_list(&mut config, format, verbose).await?
+                    }
+                    IcsCommands::Add {
+                        name,
+                        link,
+                        description,
+                        tags,
+                        default_month,
+                        default_year,
+                        caldav_username,
+                        caldav_password,
+                        no_verify_ssl,
+                        default_cost_center,
+                        billable_marker,
+                        test,
+                        yes,
+                    } => {
+                        let source = match (caldav_username, caldav_password) {
+                            (Some(username), Some(password)) => {
+                                IcsSource::CalDav { username, password }
+                            }
+                            _ => IcsSource::Feed,
+                        };
+
+                        let ical_config = ICalConfig {
+                            url: link,
+                            name,
+                            description,
+                            source,
+                            tags,
+                            default_month,
+                            default_year,
+                            verify_ssl: no_verify_ssl.then_some(false),
+                            billable_marker,
+                            default_cost_center,
+                            ..Default::default()
+                        };
+
+                        if test {
+                            ics_preview(&config, &ical_config).await?;
+
+                            let confirmed = yes
+                                || !std::io::stdin().is_terminal()
+                                || Confirm::new()
+                                    .with_prompt("Add this calendar?")
+                                    .default(false)
+                                    .interact()?;
+                            if !confirmed {
+                                return Ok(());
+                            }
+                        }
+
+                        ics_add(&mut config, ical_config).await?
+                    }
                     IcsCommands::Remove { index } => ics_remove(&mut config, index).await?,
+                    IcsCommands::Update {
+                        index,
+                        name,
+                        url,
+                        description,
+                        billable_marker,
+                    } => {
+                        ics_update(&mut config, index, name, url, description, billable_marker)
+                            .await?
+                    }
+                    IcsCommands::Health => ics_health(&mut config).await?,
+                    IcsCommands::Duplicate { index, new_name } => {
+                        ics_duplicate(&mut config, index, new_name).await?
+                    }
+                    IcsCommands::Sort { by } => ics_sort(&mut config, by).await?,
+                }
+            }
+            ConfigureCommands::Clear { calendar, confirm } => match calendar {
+                Some(name) => {
+                    let mut config = Config::open().await?.unwrap_or_default();
+                    config_clear_calendar(&mut config, &name).await?
                 }
+                None => config_clear(confirm).await?,
+            },
+            ConfigureCommands::SetTitleMapping { pattern, canonical } => {
+                let mut config = Config::open().await?.unwrap_or_default();
+                title_mapping_set(&mut config, pattern, canonical).await?
+            }
+            ConfigureCommands::ListTitleMappings => {
+                let config = Config::open().await?.unwrap_or_default();
+                title_mapping_list(&config)
+            }
+            ConfigureCommands::RemoveTitleMapping { index } => {
+                let mut config = Config::open().await?.unwrap_or_default();
+                title_mapping_remove(&mut config, index).await?
+            }
+            ConfigureCommands::Restore { backup } => {
+                Config::restore(&backup).await?;
+                println!("Restored the configuration from {}", backup.display());
             }
-            ConfigureCommands::Clear => config_clear().await?,
         },
-        Commands::Report {
-            ics_index,
-            month,
-            year,
-            output_format,
-        } => {
+        Commands::Report(report_args) if report_args.watch => {
+            let config = Config::open().await?.unwrap_or_default();
+            watch_report(config, &report_args).await?
+        }
+        Commands::Report(report_args) if report_args.compare_previous => {
+            // The comparison is printed below the table, other formats have no place for it
+            if !matches!(report_args.output_format, OutFormat::Table)
+                || report_args.template.is_some()
+            {
+                return Err(AppError::InvalidArgs(
+                    "--compare-previous only works with the table output format".to_string(),
+                )
+                .into());
+            }
+
+            let mut config = Config::open().await?.unwrap_or_default();
+            let (summary, previous) = report_with_previous(&mut config, &report_args).await?;
+            if summary.events.is_empty() && previous.events.is_empty() && !report_args.summary_only
+            {
+                // --min-total takes precedence, with its own message and exit code
+                check_total_limits(&report_args, &summary);
+                return Err(AppError::NoEvents.into());
+            }
+
+            render_report(&report_args, &summary).await?;
+            report_print_comparison(&previous, &summary, summary.table_style);
+            check_total_limits(&report_args, &summary);
+        }
+        Commands::Report(report_args) => {
+            let mut config = Config::open().await?.unwrap_or_default();
+            let summary = report(&mut config, &report_args).await?;
+            if report_args.dry_run {
+                report_print_dry_run(&summary);
+            } else {
+                // --summary-only still reports an empty period, as `0 events`
+                if summary.events.is_empty() && !report_args.summary_only {
+                    // --min-total takes precedence, with its own message and exit code
+                    check_total_limits(&report_args, &summary);
+                    return Err(AppError::NoEvents.into());
+                }
+
+                render_report(&report_args, &summary).await?;
+                check_total_limits(&report_args, &summary);
+            }
+        }
+        Commands::Invoice(invoice_args) => {
             let mut config = Config::open().await?.unwrap_or_default();
-            report(&mut config, ics_index, month, year, output_format).await?
+            let summary = report(&mut config, &invoice_args.report).await?;
+            if summary.events.is_empty() {
+                return Err(AppError::NoEvents.into());
+            }
+
+            invoice(&invoice_args, &summary).await?
         }
+        Commands::Version => print_version(),
     };
 
     Ok(())
 }
 
-async fn config_clear() -> Result<()> {
+fn print_version() {
+    let commit_time = env!("BUILD_COMMIT_TIMESTAMP")
+        .parse()
+        .ok()
+        .and_then(|timestamp| DateTime::from_timestamp(timestamp, 0))
+        .map(|commit_time| commit_time.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+
+    println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    println!("commit:  {}", env!("BUILD_GIT_HASH"));
+    println!("date:    {commit_time}");
+    println!("target:  {}", env!("BUILD_TARGET"));
+    println!("rustc:   {}", env!("BUILD_RUSTC_VERSION"));
+}
+
+#[instrument]
+async fn config_clear(confirm: bool) -> Result<()> {
+    if !confirm {
+        if !std::io::stdin().is_terminal() {
+            return Err(AppError::InvalidArgs(
+                "This removes all configuration. Use --confirm to continue".to_string(),
+            )
+            .into());
+        }
+
+        let confirmed = Confirm::new()
+            .with_prompt("This removes all configuration. Continue?")
+            .default(false)
+            .interact()?;
+        if !confirmed {
+            return Ok(());
+        }
+    }
+
+    let backup_path = Config::backup().await?;
+    println!(
+        "Backed up the configuration to {}. Use `hour-calc configure restore <backup>` to restore it",
+        backup_path.display()
+    );
+
     Config::clear().await
 }
 
-async fn ics_list(config: &mut Config) -> Result<()> {
-    #[derive(Tabled)]
+#[instrument(skip(config))]
+async fn config_clear_calendar(config: &mut Config, name: &str) -> Result<()> {
+    let ical_config = config.ical.shift_remove(name).ok_or(AppError::InvalidArgs(
+        "No calendar with this name".to_string(),
+    ))?;
+    if let Some(path) = &ical_config.cached_content_path {
+        config.remove_cached_content(path).await?;
+    }
+
+    config.store().await
+}
+
+/// Index of the `URL` column in `ics list`
+const ICS_LIST_URL_COLUMN: usize = 2;
+/// Width the URLs are wrapped at in `ics list --verbose`
+const ICS_LIST_URL_WIDTH: usize = 40;
+/// Index of the first column only shown by `ics list --verbose`
+const ICS_LIST_VERBOSE_COLUMNS: usize = 6;
+
+#[instrument(skip(config))]
+async fn ics_list(config: &mut Config, format: ListFormat, verbose: bool) -> Result<()> {
+    #[derive(Tabled, Serialize)]
     struct IcsList<'a> {
         #[tabled(rename = "Index")]
         index: usize,
@@ -76,220 +304,2958 @@ async fn ics_list(config: &mut Config) -> Result<()> {
         name: &'a str,
         #[tabled(rename = "URL")]
         url: &'a str,
+        #[tabled(rename = "Description")]
+        description: &'a str,
+        #[tabled(rename = "Tags")]
+        #[serde(skip)]
+        tags: String,
+        #[tabled(rename = "Health")]
+        #[serde(skip)]
+        health: &'a str,
+        #[tabled(rename = "Last Checked")]
+        #[serde(skip)]
+        last_checked: String,
+        #[tabled(rename = "Last Events")]
+        #[serde(skip)]
+        last_event_count: String,
+        #[tabled(rename = "Last Total")]
+        #[serde(skip)]
+        last_total_duration: String,
     }
 
     let ics = config
         .ical
-        .iter()
+        .values()
         .enumerate()
         .map(|(index, ical_config)| IcsList {
             index,
             name: &ical_config.name,
             url: &ical_config.url,
+            description: ical_config.description.as_deref().unwrap_or_default(),
+            tags: ical_config.tags.join(", "),
+            health: match &ical_config.last_health_check {
+                Some(status) if status.healthy => "✓",
+                Some(_) => "✗",
+                None => "",
+            },
+            last_checked: ical_config
+                .last_health_check
+                .as_ref()
+                .map(|status| status.checked_at.format("%d-%m-%Y %H:%M:%S").to_string())
+                .unwrap_or_default(),
+            last_event_count: ical_config
+                .last_event_count
+                .map(|count| count.to_string())
+                .unwrap_or_default(),
+            last_total_duration: ical_config
+                .last_total_duration
+                .map(fmt_duration)
+                .unwrap_or_default(),
         })
         .collect::<Vec<_>>();
 
-    let table = Table::new(ics.iter()).with(Style::rounded()).to_string();
-    println!("{table}");
+    match format {
+        ListFormat::Table => {
+            let mut table = Table::new(ics.iter());
+            style_table(&mut table, TableStyle::from_config(config));
+            if verbose {
+                // Long URLs would make the table too wide for most terminals
+                table.with(
+                    Modify::new(Columns::single(ICS_LIST_URL_COLUMN))
+                        .with(Width::wrap(ICS_LIST_URL_WIDTH).keep_words()),
+                );
+            } else {
+                table.with(Disable::column(Columns::new(ICS_LIST_VERBOSE_COLUMNS..)));
+            }
+            println!("{table}");
+        }
+        ListFormat::Json => println!("{}", serde_json::to_string_pretty(&ics)?),
+        ListFormat::Csv => print!("{}", csv::generate_calendar_csv(config.ical.values())),
+    }
+
     Ok(())
 }
 
-async fn ics_add(config: &mut Config, name: String, link: String) -> Result<()> {
-    if config.ical.iter().find(|x| x.name.eq(&name)).is_some() {
-        return Err(Error::msg("Already exists"));
+#[instrument(skip_all, fields(name = %ical_config.name))]
+async fn ics_add(config: &mut Config, ical_config: ICalConfig) -> Result<()> {
+    if config.ical.contains_key(&ical_config.name) {
+        return Err(AppError::InvalidArgs("Already exists".to_string()).into());
     }
 
-    config.ical.push(ICalConfig { url: link, name });
-
+    config.ical.insert(ical_config.name.clone(), ical_config);
     config.store().await
 }
 
+/// The number of events shown by `ics add --test`
+const ICS_PREVIEW_EVENTS: usize = 5;
+
+/// Fetch and parse a calendar, printing its first events and the number of events
+#[instrument(skip_all, fields(calendar = %ical_config.name))]
+async fn ics_preview(config: &Config, ical_config: &ICalConfig) -> Result<()> {
+    #[derive(Tabled)]
+    struct IcsPreview<'a> {
+        #[tabled(rename = "Date")]
+        date: &'a str,
+        #[tabled(rename = "Time")]
+        time: &'a str,
+        #[tabled(rename = "Summary")]
+        summary: &'a str,
+    }
+
+    let client = http_client(
+        config.user_agent.as_deref(),
+        ical_config.verify_ssl.unwrap_or(true),
+    )?;
+    let (mut events, _) = fetch_events(&client, ical_config, None, FetchOptions::default()).await?;
+    events.sort_by_key(|event| event.start);
+
+    let preview = events
+        .iter()
+        .take(ICS_PREVIEW_EVENTS)
+        .map(|event| IcsPreview {
+            date: &event.date,
+            time: &event.time,
+            summary: &event.title,
+        })
+        .collect::<Vec<_>>();
+
+    let mut table = Table::new(preview.iter());
+    style_table(&mut table, TableStyle::from_config(config));
+    println!("{table}");
+    println!("Found {} events in {}", events.len(), ical_config.name);
+
+    Ok(())
+}
+
+#[instrument(skip(config))]
 async fn ics_remove(config: &mut Config, index: usize) -> Result<()> {
     if index >= config.ical.len() {
-        return Err(Error::msg("Invalid index"));
+        return Err(AppError::InvalidArgs("Invalid index".to_string()).into());
     }
 
-    config.ical.remove(index);
+    if let Some((_, ical_config)) = config.ical.shift_remove_index(index) {
+        if let Some(path) = &ical_config.cached_content_path {
+            config.remove_cached_content(path).await?;
+        }
+    }
     config.store().await
 }
 
-async fn report(
+#[instrument(skip(config))]
+async fn ics_duplicate(config: &mut Config, index: usize, new_name: String) -> Result<()> {
+    let (_, ical_config) = config
+        .ical
+        .get_index(index)
+        .ok_or(AppError::InvalidArgs("Invalid index".to_string()))?;
+    let ical_config = ical_config.clone();
+
+    ics_add(
+        config,
+        ICalConfig {
+            name: new_name,
+            ..ical_config
+        },
+    )
+    .await
+}
+
+#[instrument(skip(config))]
+async fn ics_sort(config: &mut Config, by: IcsSortBy) -> Result<()> {
+    match by {
+        IcsSortBy::Name => config
+            .ical
+            .sort_by(|_, a, _, b| a.name.to_lowercase().cmp(&b.name.to_lowercase())),
+        IcsSortBy::Url => config.ical.sort_by(|_, a, _, b| a.url.cmp(&b.url)),
+    }
+    config.store().await?;
+
+    eprintln!("Warning: the calendars have been renumbered, previously used --ics-index values may now refer to a different calendar");
+    ics_list(config, ListFormat::Table, false).await
+}
+
+#[instrument(skip(config, url))]
+async fn ics_update(
     config: &mut Config,
-    ics_index: usize,
-    month: Option<u32>,
-    year: Option<i32>,
-    out_format: OutFormat,
+    index: usize,
+    name: Option<String>,
+    url: Option<String>,
+    description: Option<String>,
+    billable_marker: Option<String>,
 ) -> Result<()> {
-    let ics_config = config
+    if let Some(name) = &name {
+        if config
+            .ical
+            .get_index_of(name)
+            .is_some_and(|idx| idx != index)
+        {
+            return Err(AppError::InvalidArgs("Already exists".to_string()).into());
+        }
+    }
+
+    if index >= config.ical.len() {
+        return Err(AppError::InvalidArgs("Invalid index".to_string()).into());
+    }
+
+    if let Some(name) = name {
+        // Re-key the calendar under its new name, keeping its position
+        if let Some((_, mut ical_config)) = config.ical.shift_remove_index(index) {
+            ical_config.name = name.clone();
+            config.ical.shift_insert(index, name, ical_config);
+        }
+    }
+
+    let (_, ical_config) = config
         .ical
-        .get(ics_index)
-        .ok_or(Error::msg("Invalid index"))?;
-    let parser = download_ical(&ics_config.url).await?;
+        .get_index_mut(index)
+        .ok_or(AppError::InvalidArgs("Invalid index".to_string()))?;
 
-    // An ics file can contain multiple calendars, we just sum them up
-    let events = parser
-        .into_iter()
-        .map(|ical| {
-            let ical = ical?;
+    let mut stale_cache = None;
+    if let Some(url) = url {
+        ical_config.url = url;
+        // The previous health check, redirect and download don't apply to the new URL
+        ical_config.last_health_check = None;
+        ical_config.resolved_url = None;
+        ical_config.etag = None;
+        ical_config.last_modified = None;
+        stale_cache = ical_config.cached_content_path.take();
+    }
 
-            // Sum up every event in the calendar
-            let event_summaries = ical
-                .events
-                .iter()
-                .map(|event| {
-                    // Get the start property
-                    let dtstart = event.properties.iter().find(|prop| prop.name.eq("DTSTART"));
-
-                    let dtstart = match dtstart {
-                        Some(x) if x.value.is_some() => x.value.clone().unwrap(),
-                        Some(_) | None => {
-                            warn!("Event is missing start property, skipping!");
-                            return Ok(None);
-                        }
-                    };
-
-                    // Get the end property
-                    let dtend = event.properties.iter().find(|prop| prop.name.eq("DTEND"));
-                    let dtend = match dtend {
-                        Some(x) if x.value.is_some() => x.value.clone().unwrap(),
-                        Some(_) | None => {
-                            warn!("Event is missing end property, skipping!");
-                            return Ok(None);
-                        }
-                    };
-
-                    // Convert both to DateTime
-                    let start = hypentate_dttime(&dtstart);
-                    let start = DateTime::parse_from_rfc3339(&start)?;
-                    let end = hypentate_dttime(&dtend);
-                    let end = DateTime::parse_from_rfc3339(&end)?;
-
-                    // Format the event date as DD-MM-YYYY - DD-MM-YYYY
-                    // Account for if the date spans multiple days
-                    let date = if start.day() == end.day() {
-                        format!("{:02}-{:02}-{}", start.day(), start.month(), start.year())
-                    } else {
-                        format!(
-                            "{:02}-{:02}-{} - {:02}-{:02}-{}",
-                            start.day(),
-                            start.month(),
-                            start.year(),
-                            end.day(),
-                            end.month(),
-                            end.year()
-                        )
-                    };
-
-                    // Format the event timespan as HH:MM:SS - HH:MM:SS
-                    let time = format!(
-                        "{:02}:{:02} - {:02}:{:02}",
-                        start.hour(),
-                        start.minute(),
-                        end.hour(),
-                        end.minute()
-                    );
-
-                    let duration = end - start;
-                    Ok(Some(EventSummary {
-                        date,
-                        time,
-                        duration: fmt_duration(duration.num_seconds()),
-                        duration_sec: duration.num_seconds(),
-                        date_start: start.day(),
-                        month_start: start.month(),
-                        year_start: start.year(),
-                    }))
-                })
-                .collect::<Result<Vec<_>>>()?
-                .into_iter()
-                .filter_map(|x| x)
-                .collect::<Vec<_>>();
-            Ok(event_summaries)
+    if let Some(description) = description {
+        ical_config.description = Some(description);
+    }
+
+    if let Some(billable_marker) = billable_marker {
+        ical_config.billable_marker = Some(billable_marker);
+    }
+
+    if let Some(path) = stale_cache {
+        config.remove_cached_content(&path).await?;
+    }
+    config.store().await
+}
+
+#[instrument(skip_all)]
+async fn ics_health(config: &mut Config) -> Result<()> {
+    #[derive(Tabled)]
+    struct IcsHealth<'a> {
+        #[tabled(rename = "Index")]
+        index: usize,
+        #[tabled(rename = "Name")]
+        name: &'a str,
+        #[tabled(rename = "Status")]
+        status: &'a str,
+        #[tabled(rename = "Response Time")]
+        response_time: String,
+        #[tabled(rename = "Last Checked")]
+        last_checked: String,
+    }
+
+    // Check all calendars concurrently
+    let handles = config
+        .ical
+        .values()
+        .map(|ical_config| {
+            let client = http_client(
+                config.user_agent.as_deref(),
+                ical_config.verify_ssl.unwrap_or(true),
+            )?;
+            let ical_config = ical_config.clone();
+            Ok(tokio::spawn(async move {
+                check_health(&client, &ical_config).await
+            }))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    for (ical_config, handle) in config.ical.values_mut().zip(handles) {
+        let (health, token) = handle.await?;
+        ical_config.last_health_check = Some(health);
+        // Cache a refreshed OAuth 2.0 token, like a report does
+        if let Some(token) = token {
+            ical_config.cached_token = Some(token);
+        }
+    }
+
+    let health = config
+        .ical
+        .values()
+        .enumerate()
+        .filter_map(|(index, ical_config)| {
+            let status = ical_config.last_health_check.as_ref()?;
+            Some(IcsHealth {
+                index,
+                name: &ical_config.name,
+                status: &status.status,
+                response_time: format!("{} ms", status.response_time_ms),
+                last_checked: status.checked_at.format("%d-%m-%Y %H:%M:%S").to_string(),
+            })
         })
-        .collect::<Result<Vec<_>>>()?
-        .into_iter()
-        .flatten()
         .collect::<Vec<_>>();
 
-    let mut events = events
-        .into_iter()
-        .filter(|event| {
-            month
-                .map(|month| event.month_start == month)
-                .unwrap_or(true)
+    let mut table = Table::new(health.iter());
+    style_table(&mut table, TableStyle::from_config(config));
+    println!("{table}");
+
+    let unhealthy = config
+        .ical
+        .values()
+        .enumerate()
+        .filter(|(_, ical_config)| {
+            ical_config
+                .last_health_check
+                .as_ref()
+                .map(|status| !status.healthy)
+                .unwrap_or(false)
         })
-        .filter(|event| year.map(|year| event.year_start == year).unwrap_or(true))
         .collect::<Vec<_>>();
+    for (index, ical_config) in unhealthy {
+        println!(
+            "{} is unreachable. If its URL changed, use `hour-calc configure ics update {index} --url <URL>`",
+            ical_config.name
+        );
+    }
+
+    config.store().await
+}
 
-    // Sort by date
-    events.sort_by(|a, b| a.date_start.cmp(&b.date_start));
+/// Check whether a calendar responds successfully, authenticating the same way as when downloading it.
+/// Also returns the OAuth 2.0 token if it had to be refreshed, so it can be cached
+#[instrument(skip_all, fields(calendar = %ics_config.name))]
+async fn check_health(
+    client: &Client,
+    ics_config: &ICalConfig,
+) -> (HealthStatus, Option<CachedToken>) {
+    let checked_at = Utc::now();
+    let url = &ics_config.url;
 
-    match out_format {
-        OutFormat::Table => report_print_table(&events),
-        OutFormat::Pdf => pdf::generate_pdf(&ics_config.name, &events).await?,
+    if url.eq(STDIN_URL) || url.starts_with(FILE_URL_PREFIX) {
+        let health = HealthStatus {
+            healthy: true,
+            status: "Local".to_string(),
+            response_time_ms: 0,
+            checked_at,
+        };
+        return (health, None);
     }
 
-    Ok(())
+    let url = ics_config.request_url();
+    let started = Instant::now();
+    let mut token = None;
+    let response = async {
+        let request = match &ics_config.source {
+            // A collection has no content to GET, so ask for its properties instead
+            IcsSource::CalDav { username, password } => client
+                .request(Method::from_bytes(b"PROPFIND")?, url)
+                .basic_auth(username, Some(password))
+                .header("Depth", "0"),
+            IcsSource::Feed => {
+                let (credentials, refreshed_token) =
+                    resolve_credentials(client, ics_config).await?;
+                token = refreshed_token;
+                authorize(client.get(url), credentials.as_ref())
+            }
+        };
+
+        // Only the headers are received, the body isn't downloaded
+        Ok::<_, Error>(request.send().await?)
+    }
+    .await;
+    let response_time_ms = started.elapsed().as_millis();
+
+    let health = match response {
+        Ok(response) => HealthStatus {
+            healthy: response.status().is_success(),
+            status: response.status().to_string(),
+            response_time_ms,
+            checked_at,
+        },
+        Err(e) => HealthStatus {
+            healthy: false,
+            status: e.to_string(),
+            response_time_ms,
+            checked_at,
+        },
+    };
+
+    (health, token)
 }
 
-pub fn calc_total_duration(events: &[EventSummary]) -> i64 {
-    events.iter().map(|x| x.duration_sec).sum()
+#[instrument(skip(config))]
+async fn title_mapping_set(config: &mut Config, pattern: String, canonical: String) -> Result<()> {
+    // Make sure the pattern is valid now, rather than when running a report
+    Regex::new(&pattern)?;
+
+    config.normalize_titles.insert(pattern, canonical);
+    config.store().await
 }
 
-fn report_print_table(events: &[EventSummary]) {
-    // Pretty-print as a table
-    // Adding an empty row and a footer at the bottom
-    // to display the total time
-    let table = Table::new(events.iter())
-        .with(Style::rounded())
-        .with(Panel::horizontal(events.len() + 1).column(2))
-        .with(Panel::horizontal(events.len() + 2).column(2).text(format!(
-            "Total: {} (HH:MM:SS)",
-            fmt_duration(calc_total_duration(events))
-        )))
-        .to_string();
+/// The title mappings, in the order they are applied and listed
+fn sorted_title_mappings(config: &Config) -> Vec<(&String, &String)> {
+    let mut mappings = config.normalize_titles.iter().collect::<Vec<_>>();
+    mappings.sort();
+    mappings
+}
+
+fn title_mapping_list(config: &Config) {
+    #[derive(Tabled)]
+    struct TitleMappingList<'a> {
+        #[tabled(rename = "Index")]
+        index: usize,
+        #[tabled(rename = "Pattern")]
+        pattern: &'a str,
+        #[tabled(rename = "Canonical")]
+        canonical: &'a str,
+    }
+
+    let mappings = sorted_title_mappings(config)
+        .into_iter()
+        .enumerate()
+        .map(|(index, (pattern, canonical))| TitleMappingList {
+            index,
+            pattern,
+            canonical,
+        })
+        .collect::<Vec<_>>();
 
+    let mut table = Table::new(mappings.iter());
+    style_table(&mut table, TableStyle::from_config(config));
     println!("{table}");
 }
 
-async fn download_ical(url: &str) -> Result<IcalParser<BufReader<Cursor<Vec<u8>>>>> {
-    let body_bytes = Client::new()
-        .get(url)
-        .send()
-        .await?
-        .error_for_status()?
-        .bytes()
-        .await?
-        .to_vec();
+#[instrument(skip(config))]
+async fn title_mapping_remove(config: &mut Config, index: usize) -> Result<()> {
+    let pattern = sorted_title_mappings(config)
+        .get(index)
+        .map(|(pattern, _)| pattern.to_string())
+        .ok_or(AppError::InvalidArgs("Invalid index".to_string()))?;
+
+    config.normalize_titles.remove(&pattern);
+    config.store().await
+}
 
-    Ok(IcalParser::new(BufReader::new(Cursor::new(body_bytes))))
+/// The events of a report, after filtering and sorting
+#[derive(Debug)]
+pub struct ReportSummary {
+    pub events: Vec<EventSummary>,
+    pub total_duration: i64,
+    pub event_count: usize,
+    /// The name of the report, either the calendar or the tag
+    pub calendar_name: String,
+    /// The names of all calendars the events were fetched from
+    pub calendars: Vec<String>,
+    /// The URLs of the calendars, in the same order as `calendars`
+    pub calendar_urls: Vec<String>,
+    /// The number of events before filtering
+    pub fetched_event_count: usize,
+    /// The month filtered on, from the arguments or the defaults of the calendar
+    pub month: Option<u32>,
+    /// The year filtered on, from the arguments or the defaults of the calendar
+    pub year: Option<i32>,
+    /// The week filtered on, from the arguments
+    pub week: Option<u32>,
+    /// The day weeks start on, from the arguments or the config
+    pub week_start: StartOfWeek,
+    /// How tables are drawn, from the arguments or the config
+    pub table_style: TableStyle,
+    /// The columns of the report table, from the arguments or the config
+    pub columns: Vec<TableColumn>,
+    /// The number of events left out by `--billable-only`
+    pub non_billable_count: usize,
+    /// From the arguments, or the default of the calendar
+    pub cost_center: Option<String>,
+    /// The total before applying `--per-day-cap`, if given
+    pub uncapped_duration: Option<i64>,
 }
 
-/// Format a duration in seconds as HH:MM:SS
-pub fn fmt_duration(secs: i64) -> String {
-    format!(
-        "{:02}:{:02}:{:02}",
-        (secs / 60) / 60,
-        (secs / 60) % 60,
-        secs % 60
-    )
+async fn report(config: &mut Config, args: &ReportArgs) -> Result<ReportSummary> {
+    let (summary, _) = report_periods(config, args, None).await?;
+    Ok(summary)
 }
 
-/// Insert hyphens and colons into the dttime string
-/// E.g 20220921T151530Z will become 2022-09-21T15:15:30Z
-fn hypentate_dttime(input: &str) -> String {
-    let mut buf = String::new();
-    for (idx, char) in input.chars().enumerate() {
-        buf.push(char);
+/// Fetch the calendars once, and report on the period of `args`.
+/// If `previous` is given, also report on its period using the same events.
+/// Only the report on `args` updates the calendar stats
+#[instrument(skip_all, fields(ics_index = ?args.ics_index, month = args.month, year = args.year))]
+async fn report_periods(
+    config: &mut Config,
+    args: &ReportArgs,
+    previous: Option<&ReportArgs>,
+) -> Result<(ReportSummary, Option<ReportSummary>)> {
+    // A calendar read from stdin or a local file, instead of a configured one
+    let ics_file_config = args.ics_file.as_ref().map(|ics_file| {
+        if ics_file.eq(STDIN_URL) {
+            ICalConfig {
+                name: "stdin".to_string(),
+                url: STDIN_URL.to_string(),
+                ..Default::default()
+            }
+        } else {
+            ICalConfig {
+                name: Path::new(ics_file)
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().to_string())
+                    .unwrap_or_else(|| ics_file.clone()),
+                url: format!("{FILE_URL_PREFIX}{ics_file}"),
+                ..Default::default()
+            }
+        }
+    });
+
+    // Either report on the file, every calendar with the tag, all calendars,
+    // or the selected calendars
+    let calendars = match (&ics_file_config, &args.tag) {
+        (Some(ics_file_config), _) => vec![ics_file_config],
+        (None, None) if args.all => {
+            if config.ical.is_empty() {
+                return Err(Error::msg(
+                    "No calendars configured. Use `hour-calc configure ics add` to add one",
+                ));
+            }
+
+            if let Some(name) = args
+                .exclude_calendar
+                .iter()
+                .find(|name| !config.ical.contains_key(*name))
+            {
+                return Err(
+                    AppError::InvalidArgs(format!("No calendar with the name {name}")).into(),
+                );
+            }
+
+            // Leave out the excluded calendars now, so they aren't downloaded
+            let calendars = config
+                .ical
+                .values()
+                .filter(|ical_config| !args.exclude_calendar.contains(&ical_config.name))
+                .collect::<Vec<_>>();
+            if calendars.is_empty() {
+                return Err(AppError::InvalidArgs("All calendars are excluded".to_string()).into());
+            }
+
+            calendars
+        }
+        (None, Some(tag)) => {
+            let calendars = config
+                .ical
+                .values()
+                .filter(|ical_config| ical_config.tags.contains(tag))
+                .collect::<Vec<_>>();
+            if calendars.is_empty() {
+                return Err(AppError::InvalidArgs("No calendars with this tag".to_string()).into());
+            }
+
+            calendars
+        }
+        (None, None) => {
+            let ics_indices = if args.ics_index.is_empty() {
+                vec![pick_calendar(config, args.non_interactive)?]
+            } else {
+                // Keep the order the user gave, but fetch every calendar only once
+                let mut ics_indices = args.ics_index.clone();
+                let mut seen = HashSet::new();
+                ics_indices.retain(|ics_index| seen.insert(*ics_index));
+                ics_indices
+            };
+
+            ics_indices
+                .into_iter()
+                .map(|ics_index| {
+                    config
+                        .ical
+                        .get_index(ics_index)
+                        .map(|(_, ical_config)| ical_config)
+                        .ok_or(AppError::InvalidArgs(format!("Invalid index {ics_index}")))
+                })
+                .collect::<Result<Vec<_>>>()?
+        }
+    };
+
+    let report_name = match (&ics_file_config, &args.tag) {
+        (None, Some(tag)) => tag.clone(),
+        (None, None) if args.all => "all".to_string(),
+        _ => calendars[0].name.clone(),
+    };
+
+    let user_agent = args.user_agent.as_deref().or(config.user_agent.as_deref());
+    let client = http_client(user_agent, true)?;
+
+    let verify_ssl =
+        |ics_config: &ICalConfig| !args.no_verify_ssl && ics_config.verify_ssl.unwrap_or(true);
+    let insecure_client = if calendars.iter().any(|ics_config| !verify_ssl(ics_config)) {
+        Some(http_client(user_agent, false)?)
+    } else {
+        None
+    };
+    for ics_config in calendars
+        .iter()
+        .filter(|ics_config| !verify_ssl(ics_config))
+    {
+        eprintln!(
+            "Warning: not verifying the TLS certificate of {}. Anyone on the network could tamper with it",
+            ics_config.name
+        );
+    }
+
+    let (credentials, refreshed_tokens): (Vec<_>, Vec<_>) = try_join_all(
+        calendars
+            .iter()
+            .map(|ics_config| resolve_credentials(&client, ics_config)),
+    )
+    .await?
+    .into_iter()
+    .unzip();
+
+    // Fetch all calendars concurrently
+    let (events, downloads): (Vec<_>, Vec<_>) = try_join_all(
+        calendars
+            .iter()
+            .zip(&credentials)
+            .map(|(ics_config, credentials)| {
+                let client = match &insecure_client {
+                    Some(insecure_client) if !verify_ssl(ics_config) => insecure_client,
+                    _ => &client,
+                };
+                fetch_events(client, ics_config, credentials.as_ref(), args.into())
+            }),
+    )
+    .await?
+    .into_iter()
+    .unzip();
+    let mut events = events.into_iter().flatten().collect::<Vec<_>>();
 
-        if idx == 3 || idx == 5 {
-            buf.push('-');
+    // Calendars sharing events, like a personal and a team calendar, would count them twice.
+    // Occurrences of a recurring event share their UID, so the start is part of the key
+    if calendars.len() > 1 && !args.no_dedup {
+        let before = events.len();
+        let mut seen = HashSet::new();
+        events.retain(|event| match &event.uid {
+            Some(uid) => seen.insert((uid.clone(), event.start)),
+            None => true,
+        });
+        let removed = before - events.len();
+        if removed > 0 {
+            info!("Removed {removed} duplicate events with the same UID and start");
         }
+    }
+
+    let fetched_event_count = events.len();
+
+    if args.normalize_titles {
+        normalize_titles(config, &mut events)?;
+    }
+
+    let previous = previous.map(|previous| {
+        select_events(
+            config,
+            previous,
+            &calendars,
+            events.clone(),
+            fetched_event_count,
+        )
+    });
+    let Selection {
+        mut events,
+        month,
+        year,
+        week,
+        week_start,
+        non_billable_count,
+        uncapped_duration,
+    } = select_events(config, args, &calendars, events, fetched_event_count);
 
-        if idx == 10 || idx == 12 {
-            buf.push(':');
+    if args.annotate && !args.dry_run {
+        annotate_events(&mut events)?;
+    }
+
+    let calendar_names = calendars
+        .iter()
+        .map(|ics_config| ics_config.name.clone())
+        .collect();
+    let calendar_urls = calendars
+        .iter()
+        .map(|ics_config| ics_config.url.clone())
+        .collect();
+    let updates = calendars
+        .iter()
+        .map(|ics_config| ics_config.name.clone())
+        .zip(refreshed_tokens.into_iter().zip(downloads))
+        .collect::<Vec<_>>();
+
+    // Remember the results of every calendar for `ics list --verbose`,
+    // and cache the refreshed tokens so they can be used until they expire.
+    // The config is only written if any of it changed
+    let update_stats = !args.dry_run && ics_file_config.is_none();
+    let mut changed = false;
+    for (name, (token, download)) in updates {
+        let Some(ical_config) = config.ical.get_mut(&name) else {
+            continue;
+        };
+
+        if let Some(token) = token {
+            ical_config.cached_token = Some(token);
+            changed = true;
         }
+        // The validators describe the cached download, which is replaced even on a dry run
+        changed |= update_field(&mut ical_config.resolved_url, download.resolved_url);
+        changed |= update_field(&mut ical_config.etag, download.etag);
+        changed |= update_field(&mut ical_config.last_modified, download.last_modified);
+        changed |= update_field(
+            &mut ical_config.cached_content_path,
+            download.cached_content_path,
+        );
+        if !update_stats {
+            continue;
+        }
+
+        let calendar_events = events
+            .iter()
+            .filter(|event| event.calendar.eq(&name))
+            .collect::<Vec<_>>();
+        changed |= update_field(
+            &mut ical_config.last_event_count,
+            Some(calendar_events.len()),
+        );
+        changed |= update_field(
+            &mut ical_config.last_total_duration,
+            Some(calendar_events.iter().map(|event| event.duration_sec).sum()),
+        );
+    }
+
+    if changed {
+        config.store().await?;
+    }
+
+    let cost_center = args
+        .cost_center
+        .clone()
+        .or_else(|| match calendars.as_slice() {
+            [ics_config] => ics_config.default_cost_center.clone(),
+            _ => None,
+        });
+
+    let mut columns = args
+        .columns
+        .clone()
+        .or_else(|| config.visible_columns.clone())
+        .unwrap_or_else(|| TableColumn::DEFAULT.to_vec());
+    if args.show_event_id && !columns.contains(&TableColumn::Uid) {
+        columns.push(TableColumn::Uid);
+    }
+
+    let table_style = TableStyle {
+        color_scheme: args
+            .color_scheme
+            .or(config.color_scheme)
+            .unwrap_or_default(),
+        ascii: args.ascii || config.use_ascii_table,
+    };
+    let summary = ReportSummary {
+        total_duration: calc_total_duration(&events),
+        event_count: events.len(),
+        events,
+        calendar_name: report_name,
+        calendars: calendar_names,
+        calendar_urls,
+        fetched_event_count,
+        month,
+        year,
+        week,
+        week_start,
+        non_billable_count,
+        cost_center,
+        uncapped_duration,
+        columns,
+        table_style,
+    };
+    let previous = previous.map(|selection| ReportSummary {
+        total_duration: calc_total_duration(&selection.events),
+        event_count: selection.events.len(),
+        events: selection.events,
+        month: selection.month,
+        year: selection.year,
+        week: selection.week,
+        week_start: selection.week_start,
+        non_billable_count: selection.non_billable_count,
+        uncapped_duration: selection.uncapped_duration,
+        calendar_name: summary.calendar_name.clone(),
+        calendars: summary.calendars.clone(),
+        calendar_urls: summary.calendar_urls.clone(),
+        fetched_event_count,
+        cost_center: summary.cost_center.clone(),
+        columns: summary.columns.clone(),
+        table_style,
+    });
+
+    Ok((summary, previous))
+}
+
+/// Set `field` to `value`, returning whether that changed it
+fn update_field<T: PartialEq>(field: &mut T, value: T) -> bool {
+    if *field == value {
+        return false;
+    }
+
+    *field = value;
+    true
+}
+
+/// The events of a report after applying the period and other filters of `args`, sorted.
+/// Also returns the period filtered on, see the fields of [ReportSummary] with the same names
+struct Selection {
+    events: Vec<EventSummary>,
+    month: Option<u32>,
+    year: Option<i32>,
+    week: Option<u32>,
+    week_start: StartOfWeek,
+    non_billable_count: usize,
+    uncapped_duration: Option<i64>,
+}
+
+fn select_events(
+    config: &Config,
+    args: &ReportArgs,
+    calendars: &[&ICalConfig],
+    mut events: Vec<EventSummary>,
+    fetched_event_count: usize,
+) -> Selection {
+    // Without any period, fall back to the defaults of the calendar
+    let no_period = args.month.is_none()
+        && args.year.is_none()
+        && args.week.is_none()
+        && args.since.is_none()
+        && args.until.is_none()
+        && !args.today
+        && args.invoice_period_start.is_none()
+        && args.fiscal_year.is_none();
+    let (month, year) = match calendars {
+        [ics_config] if no_period => (ics_config.default_month, ics_config.default_year),
+        _ => (args.month, args.year),
+    };
+    let year = if args.any_year { None } else { year };
+
+    // This many events usually means the URL points to the wrong feed
+    if no_period && month.is_none() && year.is_none() && fetched_event_count > args.max_events {
+        eprintln!(
+            "Warning: found {fetched_event_count} events. Did you mean to use --month/--year filters?"
+        );
     }
 
-    buf
+    // Only the offset the events are displayed in changes, their start and end stay in UTC.
+    // Convert before filtering, so an event belongs to the day and month it has in the
+    // report's timezone
+    if let Some(timezone) = args.timezone {
+        for event in &mut events {
+            *event = event.in_timezone(timezone);
+        }
+    }
+
+    let week_start = args.start_of_week.unwrap_or(config.week_start);
+    let today = match args.timezone {
+        Some(timezone) => Utc::now().with_timezone(&timezone).date_naive(),
+        None => Local::now().date_naive(),
+    };
+
+    let mut events = events
+        .into_iter()
+        .filter(|event| {
+            month
+                .map(|month| event.start_date().month() == month)
+                .unwrap_or(true)
+        })
+        .filter(|event| {
+            year.map(|year| {
+                // When filtering on a week, the year refers to the ISO week year.
+                // E.g. 31-12-2024 is in week 1 of 2025
+                if args.week.is_some() {
+                    event.week(week_start).year() == year
+                } else {
+                    event.start_date().year() == year
+                }
+            })
+            .unwrap_or(true)
+        })
+        .filter(|event| {
+            args.week
+                .map(|week| event.week(week_start).week() == week)
+                .unwrap_or(true)
+        })
+        .filter(|event| {
+            args.since
+                .map(|since| event.start_date() >= since)
+                .unwrap_or(true)
+        })
+        .filter(|event| {
+            args.until
+                .map(|until| event.start_date() <= until)
+                .unwrap_or(true)
+        })
+        .filter(|event| !args.today || event.start_date() == today)
+        .filter(|event| {
+            invoice_period(args)
+                .map(|(start, end)| (start..=end).contains(&event.start_date()))
+                .unwrap_or(true)
+        })
+        .filter(|event| {
+            fiscal_year(args)
+                .map(|(start, end)| (start..=end).contains(&event.start_date()))
+                .unwrap_or(true)
+        })
+        .filter(|event| {
+            args.first_n_days
+                .map(|days| event.start_date().day() <= days)
+                .unwrap_or(true)
+        })
+        .filter(|event| !(args.exclude_meetings && event.is_meeting))
+        .filter(|event| !args.only_meetings || event.is_meeting)
+        .filter(|event| !(args.exclude_alarms && event.is_reminder()))
+        .collect::<Vec<_>>();
+
+    if !args.exclude_keywords.is_empty() {
+        let keywords = args
+            .exclude_keywords
+            .iter()
+            .map(|keyword| keyword.to_lowercase())
+            .collect::<Vec<_>>();
+
+        let before = events.len();
+        events.retain(|event| {
+            let title = event.title.to_lowercase();
+            !keywords.iter().any(|keyword| title.contains(keyword))
+        });
+        let excluded = before - events.len();
+        if excluded > 0 {
+            info!("Excluded {excluded} events matching --exclude-keywords");
+        }
+    }
+
+    let mut non_billable_count = 0;
+    if args.billable_only {
+        let markers = calendars
+            .iter()
+            .map(|ics_config| {
+                (
+                    ics_config.name.as_str(),
+                    ics_config.billable_marker.as_deref(),
+                )
+            })
+            .collect::<HashMap<_, _>>();
+
+        let before = events.len();
+        events.retain(|event| {
+            event.is_billable(markers.get(event.calendar.as_str()).copied().flatten())
+        });
+        non_billable_count = before - events.len();
+    }
+
+    let uncapped_duration = args.per_day_cap.map(|cap| {
+        let uncapped_duration = calc_total_duration(&events);
+        cap_per_day(&mut events, (cap * 3600.0).round() as i64);
+        uncapped_duration
+    });
+
+    // Sort, unless the original ICS order should be kept
+    if !args.no_sort {
+        match args.sort_by {
+            SortBy::Date => events.sort_by_key(|event| event.start),
+            SortBy::Duration => events.sort_by(|a, b| {
+                b.duration_sec
+                    .cmp(&a.duration_sec)
+                    .then(a.start.cmp(&b.start))
+            }),
+            SortBy::Name => {
+                events.sort_by(|a, b| a.title.cmp(&b.title).then(a.start.cmp(&b.start)))
+            }
+        }
+    }
+
+    Selection {
+        events,
+        month,
+        year,
+        week: args.week,
+        week_start,
+        non_billable_count,
+        uncapped_duration,
+    }
+}
+
+/// Report on the requested period and the one before it, from the same download
+async fn report_with_previous(
+    config: &mut Config,
+    args: &ReportArgs,
+) -> Result<(ReportSummary, ReportSummary)> {
+    let previous_args = previous_period(args)?;
+    let (summary, previous) = report_periods(config, args, Some(&previous_args)).await?;
+    Ok((
+        summary,
+        previous.expect("The previous period is reported on"),
+    ))
+}
+
+/// The arguments of `args` changed to the period before it: the previous week, month or year
+fn previous_period(args: &ReportArgs) -> Result<ReportArgs> {
+    let Some(year) = args.year else {
+        return Err(AppError::InvalidArgs(
+            "--compare-previous requires --year, optionally with --month or --week".to_string(),
+        )
+        .into());
+    };
+
+    let (week, month, year) = match (args.week, args.month) {
+        (Some(_), Some(_)) => {
+            return Err(AppError::InvalidArgs(
+                "--compare-previous can't be used with both --week and --month".to_string(),
+            )
+            .into())
+        }
+        (Some(week), None) => {
+            // Weeks numbered from Sunday are shifted ISO weeks, so the previous one is the same
+            let previous = NaiveDate::from_isoywd_opt(year, week, Weekday::Mon)
+                .map(|monday| (monday - Duration::days(7)).iso_week())
+                .ok_or_else(|| AppError::InvalidArgs(format!("{year} has no week {week}")))?;
+            (Some(previous.week()), None, previous.year())
+        }
+        (None, Some(1)) => (None, Some(12), year - 1),
+        (None, Some(month)) => (None, Some(month - 1), year),
+        (None, None) => (None, None, year - 1),
+    };
+
+    Ok(ReportArgs {
+        week,
+        month,
+        year: Some(year),
+        ..args.clone()
+    })
+}
+
+/// Print the totals of the previous and the current period, and the change between them
+fn report_print_comparison(
+    previous: &ReportSummary,
+    current: &ReportSummary,
+    table_style: TableStyle,
+) {
+    #[derive(Tabled)]
+    struct ComparisonRow {
+        #[tabled(rename = "Period")]
+        period: String,
+        #[tabled(rename = "Hours")]
+        hours: String,
+        #[tabled(rename = "Δ Hours")]
+        delta_hours: String,
+        #[tabled(rename = "Δ%")]
+        delta_percentage: String,
+    }
+
+    let hours = |summary: &ReportSummary| summary.total_duration as f64 / 3600.0;
+    let period = |summary: &ReportSummary| match (summary.week, summary.year) {
+        (Some(week), Some(year)) => format!("Week {week} {year}"),
+        _ => fmt_period(summary.month, summary.year).unwrap_or(summary.calendar_name.clone()),
+    };
+
+    let delta = hours(current) - hours(previous);
+    let rows = [
+        ComparisonRow {
+            period: period(previous),
+            hours: format!("{:.2}", hours(previous)),
+            delta_hours: String::new(),
+            delta_percentage: String::new(),
+        },
+        ComparisonRow {
+            period: period(current),
+            hours: format!("{:.2}", hours(current)),
+            delta_hours: format!("{delta:+.2}"),
+            delta_percentage: if previous.total_duration > 0 {
+                format!("{:+.1}%", delta / hours(previous) * 100.0)
+            } else {
+                "-".to_string()
+            },
+        },
+    ];
+
+    let mut table = Table::new(rows.iter());
+    style_table(&mut table, table_style);
+    println!("{table}");
+}
+
+/// Clear the screen and move the cursor to the top left
+const ANSI_CLEAR_SCREEN: &str = "\x1b[2J\x1b[H";
+
+/// Fetch and print the report every `--interval` seconds, until interrupted with Ctrl+C.
+/// Changes to the config file are picked up on the next refresh
+async fn watch_report(mut config: Config, args: &ReportArgs) -> Result<()> {
+    let (config_tx, mut config_rx) = tokio::sync::mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        let watch = Config::watch(move |config| {
+            // The receiver only goes away when the program exits
+            let _ = config_tx.send(config);
+        });
+        if let Err(e) = watch.await {
+            warn!("Stopped watching the config file: {e}");
+        }
+    });
+
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(args.interval));
+    loop {
+        let refresh = async {
+            interval.tick().await;
+            while let Ok(reloaded) = config_rx.try_recv() {
+                config = reloaded;
+            }
+
+            // A failed refresh, e.g. because the network is down, is retried on the next one
+            let summary = report(&mut config, args).await;
+            print!("{ANSI_CLEAR_SCREEN}");
+            match summary {
+                Ok(summary) => render_report(args, &summary).await,
+                Err(e) => {
+                    eprintln!("Error: {e}");
+                    Ok(())
+                }
+            }
+        };
+
+        tokio::select! {
+            result = refresh => result?,
+            _ = tokio::signal::ctrl_c() => {
+                println!("\nStopped watching");
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Exit with a non-zero code if the total is outside the limits given by the user
+fn check_total_limits(args: &ReportArgs, summary: &ReportSummary) {
+    if let Some(min_total) = args.min_total {
+        let total = summary.total_duration;
+        if total < min_total {
+            eprintln!(
+                "Total of {} is below the minimum of {} (HH:MM:SS)",
+                fmt_duration(total),
+                fmt_duration(min_total)
+            );
+            process::exit(2);
+        }
+    }
+
+    if let Some(max_total) = args.max_total {
+        let total = summary.total_duration;
+        if total > max_total {
+            eprintln!(
+                "Warning: total of {} exceeds the maximum of {} (HH:MM:SS)",
+                fmt_duration(total),
+                fmt_duration(max_total)
+            );
+
+            if args.fail_over_max {
+                process::exit(3);
+            }
+        }
+    }
+}
+
+/// Write text output to the output file or stdout, in the requested encoding
+#[instrument(skip_all)]
+async fn write_output(args: &ReportArgs, output: &str) -> Result<()> {
+    let bytes = encode_output(output, args.output_encoding);
+    match &args.output_file {
+        Some(output_file) => fs::write(output_file, bytes).await?,
+        None => std::io::stdout().write_all(&bytes)?,
+    }
+
+    Ok(())
+}
+
+/// Generate an invoice PDF for the events of the report
+#[instrument(skip_all, fields(invoice_number = ?args.report.invoice_number))]
+async fn invoice(args: &InvoiceArgs, summary: &ReportSummary) -> Result<()> {
+    let report_args = &args.report;
+    let invoice_date = report_args
+        .invoice_date
+        .unwrap_or_else(|| Local::now().date_naive());
+
+    let data = InvoiceData {
+        client_name: report_args
+            .client_name
+            .clone()
+            .unwrap_or_else(|| summary.calendar_name.clone()),
+        invoice_number: report_args
+            .invoice_number
+            .clone()
+            .ok_or(AppError::InvalidArgs(
+                "An invoice requires --invoice-number".to_string(),
+            ))?,
+        invoice_date,
+        due_date: args.due_date.unwrap_or(invoice_date + Duration::days(30)),
+        rate: args.rate,
+        vat_rate: args.vat_rate,
+    };
+
+    let bytes = invoice::generate_invoice_pdf(
+        &data,
+        &summary.events,
+        &pdf_options(report_args, summary, None),
+    )
+    .await?;
+    let output_path = match &report_args.output_file {
+        Some(output_file) => output_file.clone(),
+        None => env::current_dir()?.join(format!("{}.pdf", data.invoice_number)),
+    };
+    fs::write(&output_path, bytes).await?;
+    pdf_saved(report_args, &output_path)?;
+
+    Ok(())
+}
+
+/// Tell the user where the PDF was saved, and open it if requested
+fn pdf_saved(args: &ReportArgs, output_path: &Path) -> Result<()> {
+    println!("Saved PDF to {}", output_path.display());
+    if args.open {
+        opener::open(output_path)?;
+    }
+
+    Ok(())
+}
+
+fn pdf_options(
+    args: &ReportArgs,
+    summary: &ReportSummary,
+    hourly: Option<[i64; 24]>,
+) -> PdfOptions {
+    PdfOptions {
+        font_size: args.font_size,
+        font_family: args.font_family.clone(),
+        paper_size: args.paper_size,
+        orientation: args.orientation,
+        hourly,
+        invoice: InvoiceDetails {
+            client_name: args.client_name.clone(),
+            invoice_number: args.invoice_number.clone(),
+            invoice_date: args.invoice_date,
+            cost_center: summary.cost_center.clone(),
+        },
+        locale: PdfLocale::get(args.pdf_locale),
+        description_length: args.include_description.then_some(args.description_length),
+    }
+}
+
+/// Output the report in the format requested by the user
+#[instrument(skip_all, fields(output_format = ?args.output_format))]
+async fn render_report(args: &ReportArgs, summary: &ReportSummary) -> Result<()> {
+    let report_name = &summary.calendar_name;
+    let calendars = &summary.calendars;
+    let events = &summary.events;
+    let table_style = summary.table_style;
+    info!("Rendering {} events for {report_name}", summary.event_count);
+
+    if args.summary_only {
+        let period = match (invoice_period(args), fiscal_year(args)) {
+            (Some((start, end)), _) => format!("{start} to {end}"),
+            (_, Some((start, end))) => fmt_fiscal_year(start, end),
+            _ => fmt_period(summary.month, summary.year).unwrap_or(report_name.clone()),
+        };
+        println!(
+            "{period}: {} ({} events)",
+            fmt_duration(summary.total_duration),
+            summary.event_count
+        );
+        return Ok(());
+    }
+
+    if let Some(template_path) = &args.template {
+        let template = if template_path.as_path() == Path::new(template::DEFAULT_TEMPLATE_NAME) {
+            None
+        } else {
+            Some(fs::read_to_string(template_path).await?)
+        };
+
+        let rendered = template::render_template(template.as_deref(), report_name, events)?;
+        match &args.output_file {
+            Some(output_file) => fs::write(output_file, rendered).await?,
+            None => print!("{rendered}"),
+        }
+
+        return Ok(());
+    }
+
+    let hourly = args.hourly_breakdown.then(|| hourly_breakdown(events));
+
+    match args.output_format {
+        OutFormat::Table => {
+            if let Some((start, end)) = invoice_period(args) {
+                println!("Invoice Period: {start} to {end}");
+            }
+            if let Some((start, end)) = fiscal_year(args) {
+                println!("{}", fmt_fiscal_year(start, end));
+            }
+            if let Some(timezone) = args.timezone {
+                println!("Times in {timezone}");
+            }
+
+            let mut footers = Vec::new();
+            if args.deep_work_hours {
+                footers.push(format!(
+                    "Deep work: {} (HH:MM:SS)",
+                    fmt_duration(deep_work_total(events, DEEP_WORK_SESSION_MINUTES))
+                ));
+            }
+
+            if args.billable_only {
+                footers.push(format!(
+                    "Billable only, excluded {} events",
+                    summary.non_billable_count
+                ));
+            }
+
+            if let (Some(cap), Some(uncapped)) = (args.per_day_cap, summary.uncapped_duration) {
+                footers.push(format!(
+                    "Capped at {} per day, uncapped total: {} (HH:MM:SS)",
+                    fmt_duration((cap * 3600.0).round() as i64),
+                    fmt_duration(uncapped)
+                ));
+            }
+
+            if let Some(days) = args.first_n_days {
+                footers.push(match fmt_period(summary.month, summary.year) {
+                    Some(period) => format!("Showing first {days} days of {period}"),
+                    None => format!("Showing first {days} days of every month"),
+                });
+            }
+
+            let total_hours = calc_total_duration(events) as f64 / 3600.0;
+            if let Some(invoice_total) = args.invoice_total {
+                footers.push(if total_hours > 0.0 {
+                    format!("Implied rate: {:.2}/hr", invoice_total / total_hours)
+                } else {
+                    "Implied rate: no time logged".to_string()
+                });
+            }
+            if let Some(hourly_rate) = args.hourly_rate {
+                footers.push(format!("Invoice total: {:.2}", hourly_rate * total_hours));
+            }
+
+            let marked_events;
+            let events = match (args.working_hours_start, args.working_hours_end) {
+                (Some(start), Some(end)) => {
+                    if start >= end {
+                        return Err(AppError::InvalidArgs(
+                            "--working-hours-start must be before --working-hours-end".to_string(),
+                        )
+                        .into());
+                    }
+
+                    let out_of_hours = events
+                        .iter()
+                        .map(|event| out_of_hours_duration(event, start, end, args.timezone))
+                        .sum();
+                    footers.push(format!(
+                        "Out of hours: {} (HH:MM:SS)",
+                        fmt_duration(out_of_hours)
+                    ));
+
+                    marked_events = mark_out_of_hours(events, start, end, args.timezone);
+                    &marked_events
+                }
+                _ => events,
+            };
+
+            // Gaps are only meaningful between events in the order they happened
+            if args.show_gaps && (args.no_sort || !matches!(args.sort_by, SortBy::Date)) {
+                return Err(AppError::InvalidArgs(
+                    "--show-gaps requires the events to be sorted by date".to_string(),
+                )
+                .into());
+            }
+            let gap_threshold = args.show_gaps.then_some(args.gap_threshold * 60);
+            if args.split_by_calendar {
+                report_print_split(
+                    calendars,
+                    events,
+                    &table_columns(&summary.columns, args.detect_meetings, false),
+                    gap_threshold,
+                    table_style,
+                )
+            } else {
+                match args.group_by {
+                    Some(GroupBy::Date) => report_print_groups(
+                        &group_events_by_date(events),
+                        args.expand,
+                        &footers,
+                        table_style,
+                    ),
+                    Some(GroupBy::Week) => report_print_groups(
+                        &group_events_by_week(events, summary.week_start),
+                        args.expand,
+                        &footers,
+                        table_style,
+                    ),
+                    None => {
+                        let page = paginate(events, args.offset, args.limit);
+                        if page.len() < events.len() {
+                            footers.push(match page.len() {
+                                0 => format!("Displaying no events of {}", events.len()),
+                                len => format!(
+                                    "Displaying events {}-{} of {}",
+                                    args.offset + 1,
+                                    args.offset + len,
+                                    events.len()
+                                ),
+                            });
+                        }
+
+                        report_print_table(
+                            page,
+                            calc_total_duration(events),
+                            &table_columns(
+                                &summary.columns,
+                                args.detect_meetings,
+                                calendars.len() > 1,
+                            ),
+                            gap_threshold,
+                            &footers,
+                            table_style,
+                        )
+                    }
+                }
+            }
+
+            if calendars.len() > 1 && !args.split_by_calendar {
+                report_print_subtotals(calendars, events, table_style);
+            }
+            if let Some(hourly) = &hourly {
+                report_print_hourly(hourly, table_style);
+            }
+            if let Some(target) = args.week_hours_target {
+                report_print_week_targets(
+                    &group_events_by_week(events, summary.week_start),
+                    (target * 3600.0) as i64,
+                    table_style,
+                );
+            }
+            if args.histogram {
+                report_print_histogram(&duration_histogram(events, &args.histogram_buckets));
+            }
+        }
+        OutFormat::Pdf => {
+            let output_path =
+                pdf::generate_pdf(report_name, events, &pdf_options(args, summary, hourly)).await?;
+            pdf_saved(args, &output_path)?;
+        }
+        OutFormat::Org => write_output(args, &org::generate_org(report_name, events)).await?,
+        OutFormat::OrgTable => {
+            write_output(args, &org::generate_org_table(events, !args.no_header)).await?
+        }
+        OutFormat::Csv => {
+            write_output(
+                args,
+                &csv::generate_csv(events, !args.no_header, summary.cost_center.as_deref()),
+            )
+            .await?
+        }
+        OutFormat::Markdown => {
+            write_output(
+                args,
+                &markdown::generate_markdown(events, !args.no_header, args.markdown_dialect),
+            )
+            .await?
+        }
+        OutFormat::Ndjson => {
+            write_output(
+                args,
+                &json::generate_ndjson(events, summary.cost_center.as_deref())?,
+            )
+            .await?
+        }
+        OutFormat::Timew => write_output(args, &timew::generate_timew(events)).await?,
+    }
+
+    Ok(())
+}
+
+/// The first and last day of the billing period given by `--invoice-period-start` and `--invoice-period-days`
+fn invoice_period(args: &ReportArgs) -> Option<(NaiveDate, NaiveDate)> {
+    args.invoice_period_start.map(|start| {
+        (
+            start,
+            start + Duration::days(i64::from(args.invoice_period_days) - 1),
+        )
+    })
+}
+
+/// The first and last day of the fiscal year given by `--fiscal-year` and `--fiscal-year-start`
+fn fiscal_year(args: &ReportArgs) -> Option<(NaiveDate, NaiveDate)> {
+    let start = NaiveDate::from_ymd_opt(args.fiscal_year?, args.fiscal_year_start, 1)?;
+    let end = start.checked_add_months(Months::new(12))?.pred_opt()?;
+    Some((start, end))
+}
+
+/// Format a fiscal year, e.g. `FY2024: April 2024 – March 2025`
+fn fmt_fiscal_year(start: NaiveDate, end: NaiveDate) -> String {
+    let fmt_month =
+        |date: NaiveDate| fmt_period(Some(date.month()), Some(date.year())).unwrap_or_default();
+
+    format!(
+        "FY{}: {} – {}",
+        start.year(),
+        fmt_month(start),
+        fmt_month(end)
+    )
+}
+
+/// Format the month and year filtered on, e.g. `March 2024`
+fn fmt_period(month: Option<u32>, year: Option<i32>) -> Option<String> {
+    let month_name = month
+        .and_then(|month| Month::try_from(month as u8).ok())
+        .map(|month| month.name());
+
+    match (month_name, year) {
+        (Some(month_name), Some(year)) => Some(format!("{month_name} {year}")),
+        (Some(month_name), None) => Some(month_name.to_string()),
+        (None, Some(year)) => Some(year.to_string()),
+        (None, None) => None,
+    }
+}
+
+/// Encode text output for the requested character set
+fn encode_output(text: &str, encoding: OutputEncoding) -> Vec<u8> {
+    match encoding {
+        OutputEncoding::Utf8 => text.as_bytes().to_vec(),
+        OutputEncoding::Utf8Bom => {
+            // The byte order mark lets e.g. Excel detect that the file is UTF-8
+            let mut buf = vec![0xEF, 0xBB, 0xBF];
+            buf.extend_from_slice(text.as_bytes());
+            buf
+        }
+        // encoding_rs would replace characters Windows-1252 can't represent with HTML
+        // character references like `&#128197;`, which don't belong in a CSV or text file
+        OutputEncoding::Latin1 => {
+            let mut buf = Vec::with_capacity(text.len());
+            for c in text.chars() {
+                let mut utf8 = [0; 4];
+                let (bytes, _, unmappable) = WINDOWS_1252.encode(c.encode_utf8(&mut utf8));
+                if unmappable {
+                    buf.push(b'?');
+                } else {
+                    buf.extend_from_slice(&bytes);
+                }
+            }
+            buf
+        }
+    }
+}
+
+/// Let the user replace the title of every event, e.g. with a description for the client.
+/// Leaving the input empty keeps the original title
+fn annotate_events(events: &mut [EventSummary]) -> Result<()> {
+    if !std::io::stdin().is_terminal() {
+        return Err(AppError::InvalidArgs("--annotate requires a terminal".to_string()).into());
+    }
+
+    let count = events.len();
+    for (index, event) in events.iter_mut().enumerate() {
+        eprintln!(
+            "[{}/{count}] {} {} ({})",
+            index + 1,
+            event.date,
+            event.time,
+            event.duration
+        );
+
+        let annotation: String = Input::new()
+            .with_prompt(&event.title)
+            .allow_empty(true)
+            .interact_text()?;
+        let annotation = annotation.trim();
+        if !annotation.is_empty() {
+            event.title = annotation.to_string();
+        }
+    }
+
+    Ok(())
+}
+
+/// Let the user pick a calendar interactively, returning its index
+fn pick_calendar(config: &Config, non_interactive: bool) -> Result<usize> {
+    if non_interactive || !std::io::stdin().is_terminal() {
+        return Err(AppError::InvalidArgs(
+            "Missing ICS index. Use --ics-index, or run in a terminal to pick a calendar"
+                .to_string(),
+        )
+        .into());
+    }
+
+    if config.ical.is_empty() {
+        return Err(Error::msg(
+            "No calendars configured. Use `hour-calc configure ics add` to add one",
+        ));
+    }
+
+    let names = config.ical.keys().collect::<Vec<_>>();
+
+    let index = Select::new()
+        .with_prompt("Select a calendar")
+        .items(&names)
+        .default(0)
+        .interact()?;
+
+    Ok(index)
+}
+
+/// How calendars are downloaded and parsed, see the arguments of [ReportArgs] with the same names
+#[derive(Debug, Clone, Copy, Default)]
+struct FetchOptions {
+    force: bool,
+    strict: bool,
+    ignore_errors: bool,
+    /// The last day of the report period, when it can lie in the future.
+    /// Recurring events are expanded up to now, or up to this day if it is later
+    expand_until: Option<NaiveDate>,
+}
+
+impl From<&ReportArgs> for FetchOptions {
+    fn from(args: &ReportArgs) -> Self {
+        let period_ends = [
+            args.until,
+            invoice_period(args).map(|(_, end)| end),
+            fiscal_year(args).map(|(_, end)| end),
+            args.year
+                .and_then(|year| NaiveDate::from_ymd_opt(year, 12, 31)),
+        ];
+
+        Self {
+            force: args.force,
+            strict: args.strict_parse,
+            ignore_errors: args.ignore_errors,
+            expand_until: period_ends.into_iter().flatten().max(),
+        }
+    }
+}
+
+/// What to remember of the download of a calendar, see the fields of [ICalConfig] with the same names
+#[derive(Debug, Default)]
+struct Download {
+    resolved_url: Option<String>,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    cached_content_path: Option<PathBuf>,
+}
+
+/// Download a calendar and parse all of its events.
+/// Also returns what to remember of the download, like the URL the calendar was redirected to
+#[instrument(skip_all, fields(calendar = %ics_config.name))]
+async fn fetch_events(
+    client: &Client,
+    ics_config: &ICalConfig,
+    credentials: Option<&Credentials>,
+    options: FetchOptions,
+) -> Result<(Vec<EventSummary>, Download)> {
+    // A CSV report can be read back, e.g. to render it in another format
+    if let Some(path) = ics_config.url.strip_prefix(FILE_URL_PREFIX).filter(|path| {
+        Path::new(path)
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"))
+    }) {
+        let events = csv::parse_csv(&csv::decode_csv(&fs::read(path).await?))?
+            .into_iter()
+            .map(|event| EventSummary {
+                calendar: ics_config.name.clone(),
+                ..event
+            })
+            .collect();
+        return Ok((events, Download::default()));
+    }
+
+    let mut download = Download::default();
+    let body_bytes = if let IcsSource::CalDav { username, password } = &ics_config.source {
+        let (body, url) =
+            download_ical_caldav(client, ics_config.request_url(), username, password).await?;
+        if url.as_str() != ics_config.url {
+            download.resolved_url = Some(url.to_string());
+        }
+
+        body
+    } else if ics_config.url.eq(STDIN_URL) {
+        load_ical_from_reader(tokio::io::stdin()).await?
+    } else if let Some(path) = ics_config.url.strip_prefix(FILE_URL_PREFIX) {
+        load_ical_from_reader(fs::File::open(path).await?).await?
+    } else {
+        // Only ask for the calendar if it changed when the cached copy is still there
+        let cached_content_path = ics_config
+            .cached_content_path
+            .as_ref()
+            .filter(|path| path.exists());
+        let validators = cached_content_path.map(|_| Validators {
+            etag: ics_config.etag.as_deref(),
+            last_modified: ics_config.last_modified.as_deref(),
+        });
+
+        let response = download_ical(
+            client,
+            ics_config.request_url(),
+            credentials,
+            options.force,
+            validators,
+        )
+        .await?;
+        let url = match &response {
+            IcalResponse::Modified { url, .. } | IcalResponse::NotModified { url } => url,
+        };
+        if url.as_str() != ics_config.url {
+            download.resolved_url = Some(url.to_string());
+        }
+
+        match response {
+            IcalResponse::Modified {
+                body,
+                etag,
+                last_modified,
+                ..
+            } => {
+                // Servers without either header can't tell whether the calendar changed
+                if etag.is_some() || last_modified.is_some() {
+                    let path = Config::cache_path(&ics_config.url).await?;
+                    fs::write(&path, &body).await?;
+                    download.etag = etag;
+                    download.last_modified = last_modified;
+                    download.cached_content_path = Some(path);
+                } else if let Some(path) = &ics_config.cached_content_path {
+                    // The previous download is outdated, and its validators with it
+                    Config::remove_cache_file(path).await?;
+                }
+
+                body
+            }
+            IcalResponse::NotModified { .. } => {
+                let path = cached_content_path.ok_or(AppError::Network(
+                    "Server responded with 304 Not Modified, but the calendar isn't cached"
+                        .to_string(),
+                ))?;
+                debug!(
+                    "{} didn't change, using {}",
+                    ics_config.name,
+                    path.display()
+                );
+
+                download.etag = ics_config.etag.clone();
+                download.last_modified = ics_config.last_modified.clone();
+                download.cached_content_path = Some(path.clone());
+                fs::read(path).await?
+            }
+        }
+    };
+
+    // Parsing is synchronous, so run it on a blocking thread to be able to time out
+    let calendar = ics_config.name.clone();
+    let normalizations = ics_config.normalization_regexes()?;
+    let FetchOptions {
+        strict,
+        ignore_errors,
+        expand_until,
+        ..
+    } = options;
+    // Two days of margin cover the offsets of the events
+    let now = Utc::now();
+    let expand_until = expand_until
+        .map(|date| {
+            (date + Duration::days(2))
+                .and_time(NaiveTime::MIN)
+                .and_utc()
+        })
+        .map_or(now, |end| end.max(now));
+    let parse = tokio::task::spawn_blocking(move || {
+        parse_events(
+            body_bytes,
+            &calendar,
+            &normalizations,
+            strict,
+            ignore_errors,
+            expand_until,
+        )
+    });
+
+    match timeout(PARSE_TIMEOUT, parse).await {
+        Ok(events) => Ok((events??, download)),
+        Err(_) => Err(AppError::Parse(format!(
+            "Parsing calendar '{}' took longer than {} seconds",
+            ics_config.name,
+            PARSE_TIMEOUT.as_secs()
+        ))
+        .into()),
+    }
+}
+
+/// Parse the events of all calendars in an ICS file.
+/// Malformed calendars and events without a start or end are skipped, unless `strict` is set.
+/// The normalizations of the calendar are applied to the event titles.
+/// Recurring events are expanded up to `expand_until`
+fn parse_events(
+    body_bytes: Vec<u8>,
+    calendar: &str,
+    normalizations: &[(Regex, String)],
+    strict: bool,
+    ignore_errors: bool,
+    expand_until: DateTime<Utc>,
+) -> Result<Vec<EventSummary>> {
+    let parser = IcalParser::new(BufReader::new(Cursor::new(body_bytes)));
+
+    // An ics file can contain multiple calendars, we just sum them up
+    let mut events = Vec::new();
+    let mut skipped = 0;
+    let mut total_events = 0;
+    let mut failed_events = 0;
+    for ical in parser {
+        let ical = match ical {
+            Ok(ical) => ical,
+            Err(e) if !strict => {
+                warn!("Failed to parse calendar, skipping: {e}");
+                skipped += 1;
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        // Sum up every event in the calendar
+        let overridden = event::overridden_occurrences(&ical.events);
+        for event in &ical.events {
+            total_events += 1;
+            match event::occurrences(event, &overridden, expand_until) {
+                Ok(occurrences) => {
+                    events.extend(occurrences.into_iter().map(|event_summary| EventSummary {
+                        calendar: calendar.to_string(),
+                        title: apply_normalizations(&event_summary.title, normalizations),
+                        ..event_summary
+                    }))
+                }
+                Err(e @ (EventError::MissingStart | EventError::MissingEnd)) => {
+                    warn!("{e}, skipping!");
+                    skipped += 1;
+                }
+                Err(e) if ignore_errors => {
+                    let title = event::property_value(event, "SUMMARY").unwrap_or_default();
+                    eprintln!("Warning: {e} in event '{title}' of {calendar}, skipping");
+                    failed_events += 1;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    info!(
+        "Processed {} events from {calendar}, skipped {skipped}",
+        events.len()
+    );
+    if failed_events > 0 {
+        eprintln!(
+            "Processed {}/{total_events} events of {calendar} ({failed_events} skipped due to errors)",
+            total_events - failed_events
+        );
+    }
+
+    Ok(events)
+}
+
+/// Apply the normalizations of a calendar to an event title, in order
+fn apply_normalizations(title: &str, normalizations: &[(Regex, String)]) -> String {
+    normalizations
+        .iter()
+        .fold(title.to_string(), |title, (pattern, replacement)| {
+            pattern
+                .replace_all(&title, replacement.as_str())
+                .into_owned()
+        })
+}
+
+/// Replace the title of every event matching one of the configured patterns
+/// with the canonical title of that pattern.
+/// Patterns are tried in alphabetical order, the first match wins.
+fn normalize_titles(config: &Config, events: &mut [EventSummary]) -> Result<()> {
+    let mappings = sorted_title_mappings(config)
+        .into_iter()
+        .map(|(pattern, canonical)| Ok((Regex::new(pattern)?, canonical)))
+        .collect::<Result<Vec<_>>>()?;
+
+    for event in events {
+        if let Some((_, canonical)) = mappings
+            .iter()
+            .find(|(pattern, _)| pattern.is_match(&event.title))
+        {
+            event.title = canonical.to_string();
+        }
+    }
+
+    Ok(())
+}
+
+/// Sum up the time spent in every hour of the day, in seconds.
+/// Events spanning multiple hours have their duration distributed over those hours
+pub fn hourly_breakdown(events: &[EventSummary]) -> [i64; 24] {
+    let mut buckets = [0; 24];
+
+    for event in events {
+        // The hours of the day in the offset the event is displayed in
+        let end = event.local_end();
+        let mut cursor = event.local_start();
+        while cursor < end {
+            // Seconds left until the next full hour, or until the end of the event
+            let into_hour = i64::from(cursor.minute() * 60 + cursor.second());
+            let step = (3600 - into_hour).min((end - cursor).num_seconds());
+            if step <= 0 {
+                break;
+            }
+
+            buckets[cursor.hour() as usize] += step;
+            cursor += Duration::seconds(step);
+        }
+    }
+
+    buckets
+}
+
+/// Minimum length of an uninterrupted session to count as deep work
+const DEEP_WORK_SESSION_MINUTES: u32 = 90;
+
+/// Sum up the time spent in uninterrupted sessions without meetings of at least `min_session_minutes`.
+/// Back-to-back or overlapping events are merged into a single session
+pub fn deep_work_total(events: &[EventSummary], min_session_minutes: u32) -> i64 {
+    let mut solo = events
+        .iter()
+        .filter(|event| !event.is_meeting)
+        .collect::<Vec<_>>();
+    solo.sort_by_key(|event| event.start);
+
+    // Merge the events into sessions of (start, end)
+    let mut sessions: Vec<(DateTime<Utc>, DateTime<Utc>)> = Vec::new();
+    for event in solo {
+        match sessions.last_mut() {
+            Some((_, end)) if event.start <= *end => {
+                if event.end > *end {
+                    *end = event.end;
+                }
+            }
+            _ => sessions.push((event.start, event.end)),
+        }
+    }
+
+    let min_session_secs = i64::from(min_session_minutes) * 60;
+    sessions
+        .into_iter()
+        .map(|(start, end)| (end - start).num_seconds())
+        .filter(|secs| *secs >= min_session_secs)
+        .sum()
+}
+
+/// The part of an event outside the working hours from `start` to `end`, in seconds.
+/// The working hours apply to every day, in `timezone`, or the local timezone if it isn't given
+pub fn out_of_hours_duration(
+    event: &EventSummary,
+    start: NaiveTime,
+    end: NaiveTime,
+    timezone: Option<Tz>,
+) -> i64 {
+    let (event_start, event_end) = match timezone {
+        Some(timezone) => (
+            event.start.with_timezone(&timezone).naive_local(),
+            event.end.with_timezone(&timezone).naive_local(),
+        ),
+        None => (
+            event.start.with_timezone(&Local).naive_local(),
+            event.end.with_timezone(&Local).naive_local(),
+        ),
+    };
+
+    let mut in_hours = 0;
+    let mut date = event_start.date();
+    while date <= event_end.date() {
+        let overlap_start = event_start.max(date.and_time(start));
+        let overlap_end = event_end.min(date.and_time(end));
+        if overlap_end > overlap_start {
+            in_hours += (overlap_end - overlap_start).num_seconds();
+        }
+
+        date += Duration::days(1);
+    }
+
+    // A DST change during the event can make the local duration differ from the actual one
+    (event.duration_sec - in_hours).max(0)
+}
+
+/// Shorten the last events of every day taking longer than `cap` seconds, until the day takes `cap`.
+/// The times of the events are kept, only their duration changes
+fn cap_per_day(events: &mut [EventSummary], cap: i64) {
+    let mut days: BTreeMap<NaiveDate, Vec<usize>> = BTreeMap::new();
+    for (idx, event) in events.iter().enumerate() {
+        days.entry(event.start_date()).or_default().push(idx);
+    }
+
+    for mut day in days.into_values() {
+        day.sort_by_key(|idx| events[*idx].start);
+        let mut excess = day.iter().map(|idx| events[*idx].duration_sec).sum::<i64>() - cap;
+
+        for idx in day.into_iter().rev() {
+            if excess <= 0 {
+                break;
+            }
+
+            let event = &mut events[idx];
+            let reduction = excess.min(event.duration_sec.max(0));
+            event.duration_sec -= reduction;
+            event.duration = fmt_duration(event.duration_sec);
+            excess -= reduction;
+        }
+    }
+}
+
+/// Prefix the title of events which are partially or fully outside the working hours with ⚠
+fn mark_out_of_hours(
+    events: &[EventSummary],
+    start: NaiveTime,
+    end: NaiveTime,
+    timezone: Option<Tz>,
+) -> Vec<EventSummary> {
+    events
+        .iter()
+        .map(|event| {
+            if out_of_hours_duration(event, start, end, timezone) > 0 {
+                EventSummary {
+                    title: format!("⚠ {}", event.title),
+                    ..event.clone()
+                }
+            } else {
+                event.clone()
+            }
+        })
+        .collect()
+}
+
+/// Events with a duration in the range `[min_minutes, max_minutes)`
+pub struct HistogramBucket {
+    pub min_minutes: i64,
+    /// `None` for the last, unbounded, bucket
+    pub max_minutes: Option<i64>,
+    pub count: usize,
+    pub total_duration: i64,
+}
+
+/// Divide the events over buckets separated at the boundaries, in minutes
+pub fn duration_histogram(events: &[EventSummary], boundaries: &[i64]) -> Vec<HistogramBucket> {
+    let mut boundaries = boundaries.to_vec();
+    boundaries.sort_unstable();
+    boundaries.dedup();
+
+    let mut buckets = std::iter::once(0)
+        .chain(boundaries.iter().copied().filter(|boundary| *boundary > 0))
+        .collect::<Vec<_>>()
+        .windows(2)
+        .map(|range| (range[0], Some(range[1])))
+        .collect::<Vec<_>>();
+    let last = buckets.last().and_then(|(_, max)| *max).unwrap_or(0);
+    buckets.push((last, None));
+
+    buckets
+        .into_iter()
+        .map(|(min_minutes, max_minutes)| {
+            let in_bucket = events.iter().filter(|event| {
+                let minutes = event.duration_sec / 60;
+                minutes >= min_minutes && max_minutes.map(|max| minutes < max).unwrap_or(true)
+            });
+
+            HistogramBucket {
+                min_minutes,
+                max_minutes,
+                count: in_bucket.clone().count(),
+                total_duration: in_bucket.map(|event| event.duration_sec).sum(),
+            }
+        })
+        .collect()
+}
+
+pub fn calc_total_duration(events: &[EventSummary]) -> i64 {
+    events.iter().map(|x| x.duration_sec).sum()
+}
+
+/// Index of the `Meeting?` column in the report table
+const MEETING_COLUMN: usize = 4;
+/// Index of the `Calendar` column in the report table
+const CALENDAR_COLUMN: usize = 5;
+
+/// Index of a selectable column in the report table
+fn table_column_index(column: TableColumn) -> usize {
+    match column {
+        TableColumn::Date => 0,
+        TableColumn::Time => 1,
+        TableColumn::Duration => 2,
+        TableColumn::Summary => 3,
+        TableColumn::Meeting => MEETING_COLUMN,
+        TableColumn::Calendar => CALENDAR_COLUMN,
+        TableColumn::Location => 6,
+        TableColumn::Description => 7,
+        TableColumn::Uid => 8,
+    }
+}
+
+/// The selected columns, with the meeting and calendar columns added if they are relevant
+fn table_columns(
+    columns: &[TableColumn],
+    show_meetings: bool,
+    show_calendars: bool,
+) -> Vec<TableColumn> {
+    let mut columns = columns.to_vec();
+    if show_meetings && !columns.contains(&TableColumn::Meeting) {
+        columns.push(TableColumn::Meeting);
+    }
+    if show_calendars && !columns.contains(&TableColumn::Calendar) {
+        columns.push(TableColumn::Calendar);
+    }
+
+    columns
+}
+
+/// The events with a row inserted wherever an event starts at least `threshold` seconds
+/// after the previous one ends, and the indices of the inserted rows
+fn intersperse_gaps(events: &[EventSummary], threshold: i64) -> (Vec<EventSummary>, Vec<usize>) {
+    let mut rows = Vec::with_capacity(events.len());
+    let mut gap_rows = Vec::new();
+
+    for (idx, event) in events.iter().enumerate() {
+        if let Some(previous) = idx.checked_sub(1).map(|previous| &events[previous]) {
+            let gap = (event.start - previous.end).num_seconds();
+            if gap >= threshold {
+                gap_rows.push(rows.len());
+                // Nothing of the surrounding events, like their calendar or UID, belongs to the gap
+                rows.push(EventSummary {
+                    date: String::new(),
+                    time: String::new(),
+                    duration: format!("GAP: {}", fmt_duration(gap)),
+                    ..EventSummary::new(
+                        previous.local_end(),
+                        event.local_start(),
+                        "(gap)".to_string(),
+                        false,
+                    )
+                });
+            }
+        }
+
+        rows.push(event.clone());
+    }
+
+    (rows, gap_rows)
+}
+
+/// The events on the page starting at `offset`, of at most `limit` events
+fn paginate(events: &[EventSummary], offset: usize, limit: Option<usize>) -> &[EventSummary] {
+    let start = offset.min(events.len());
+    let end = limit
+        .map(|limit| start.saturating_add(limit).min(events.len()))
+        .unwrap_or(events.len());
+
+    &events[start..end]
+}
+
+/// Print the events as a table, with `total` in the footer
+fn report_print_table(
+    events: &[EventSummary],
+    total: i64,
+    columns: &[TableColumn],
+    gap_threshold: Option<i64>,
+    footers: &[String],
+    table_style: TableStyle,
+) {
+    let (rows, gap_rows) = match gap_threshold {
+        Some(threshold) => intersperse_gaps(events, threshold),
+        None => (events.to_vec(), Vec::new()),
+    };
+
+    // Pretty-print as a table
+    let mut table = Table::new(rows.iter());
+    style_table(&mut table, table_style);
+
+    // Dim the gaps in colour schemes which use colours, the header is the first row
+    if matches!(
+        table_style.color_scheme,
+        ColorScheme::Dark | ColorScheme::Light
+    ) {
+        for gap_row in gap_rows {
+            table.with(Modify::new(Rows::single(gap_row + 1)).with(Color::FG_BRIGHT_BLACK));
+        }
+    }
+
+    let mut hidden = TableColumn::value_variants()
+        .iter()
+        .filter(|column| !columns.contains(column))
+        .map(|column| table_column_index(*column))
+        .collect::<Vec<_>>();
+
+    // Disable the rightmost columns first, so the other indices stay valid
+    hidden.sort_unstable();
+    for column in hidden.into_iter().rev() {
+        table.with(Disable::column(Columns::single(column)));
+    }
+
+    add_table_footers(&mut table, rows.len(), total, footers);
+    println!("{table}");
+}
+
+/// Print a table for every calendar, followed by the grand total
+fn report_print_split(
+    calendars: &[String],
+    events: &[EventSummary],
+    columns: &[TableColumn],
+    gap_threshold: Option<i64>,
+    table_style: TableStyle,
+) {
+    for calendar in calendars {
+        let calendar_events = events
+            .iter()
+            .filter(|event| event.calendar.eq(calendar))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        println!("{calendar}");
+        report_print_table(
+            &calendar_events,
+            calc_total_duration(&calendar_events),
+            columns,
+            gap_threshold,
+            &[],
+            table_style,
+        );
+    }
+
+    println!(
+        "Grand total: {} (HH:MM:SS)",
+        fmt_duration(calc_total_duration(events))
+    );
+}
+
+/// How tables are drawn
+#[derive(Debug, Clone, Copy)]
+pub struct TableStyle {
+    pub color_scheme: ColorScheme,
+    /// Draw the borders with ASCII characters instead of Unicode box-drawing characters
+    pub ascii: bool,
+}
+
+impl TableStyle {
+    /// The table style configured by the user
+    fn from_config(config: &Config) -> Self {
+        Self {
+            color_scheme: config.color_scheme.unwrap_or_default(),
+            ascii: config.use_ascii_table,
+        }
+    }
+}
+
+/// Apply the borders and colours of the table style to a table
+fn style_table(table: &mut Table, table_style: TableStyle) {
+    match (table_style.color_scheme, table_style.ascii) {
+        (ColorScheme::None, _) => {
+            table.with(Style::blank());
+        }
+        (_, true) => {
+            table.with(Style::ascii());
+        }
+        (_, false) => {
+            table.with(Style::rounded());
+        }
+    }
+
+    match table_style.color_scheme {
+        ColorScheme::Dark => {
+            table.with(Modify::new(Rows::first()).with(Color::FG_CYAN));
+        }
+        ColorScheme::Light => {
+            table.with(Modify::new(Rows::first()).with(Color::FG_BLUE));
+        }
+        ColorScheme::Default | ColorScheme::None => {}
+    }
+}
+
+/// Add an empty row and a footer at the bottom of a table with `rows` rows,
+/// to display the total time. Any additional statistics go below the total
+fn add_table_footers(table: &mut Table, rows: usize, total: i64, footers: &[String]) {
+    table.with(Panel::horizontal(rows + 1).column(2)).with(
+        Panel::horizontal(rows + 2)
+            .column(2)
+            .text(format!("Total: {} (HH:MM:SS)", fmt_duration(total))),
+    );
+
+    for (idx, footer) in footers.iter().enumerate() {
+        table.with(
+            Panel::horizontal(rows + 3 + idx)
+                .column(2)
+                .text(footer.clone()),
+        );
+    }
+}
+
+/// All events starting in the same period
+pub struct EventGroup<'a> {
+    /// Shown in the date column of the table
+    pub label: String,
+    pub events: Vec<&'a EventSummary>,
+    pub total_duration: i64,
+}
+
+/// Group events by the date they start on, ordered by date
+pub fn group_events_by_date(events: &[EventSummary]) -> Vec<EventGroup<'_>> {
+    group_events(
+        events,
+        |event| event.start_date(),
+        |date| date.format("%d-%m-%Y").to_string(),
+    )
+}
+
+/// Group events by the week they start in, ordered by date
+pub fn group_events_by_week(
+    events: &[EventSummary],
+    week_start: StartOfWeek,
+) -> Vec<EventGroup<'_>> {
+    group_events(
+        events,
+        |event| event.start_date().week(week_start.weekday()).first_day(),
+        |date| {
+            format!(
+                "Week {} ({})",
+                event::week_of(date, week_start).week(),
+                date.format("%d-%m-%Y")
+            )
+        },
+    )
+}
+
+/// Group events by the first day of the period returned by `period`
+fn group_events<'a>(
+    events: &'a [EventSummary],
+    period: impl Fn(&EventSummary) -> NaiveDate,
+    label: impl Fn(NaiveDate) -> String,
+) -> Vec<EventGroup<'a>> {
+    let mut groups: BTreeMap<NaiveDate, Vec<&EventSummary>> = BTreeMap::new();
+    for event in events {
+        groups.entry(period(event)).or_default().push(event);
+    }
+
+    groups
+        .into_iter()
+        .map(|(date, events)| EventGroup {
+            label: label(date),
+            total_duration: events.iter().map(|event| event.duration_sec).sum(),
+            events,
+        })
+        .collect()
+}
+
+fn report_print_groups(
+    groups: &[EventGroup],
+    expand: bool,
+    footers: &[String],
+    table_style: TableStyle,
+) {
+    #[derive(Tabled)]
+    struct DayRow<'a> {
+        #[tabled(rename = "Date")]
+        date: String,
+        #[tabled(rename = "Time")]
+        time: &'a str,
+        #[tabled(rename = "Duration")]
+        duration: String,
+        #[tabled(rename = "Title")]
+        title: &'a str,
+    }
+
+    let mut rows = Vec::new();
+    for group in groups {
+        let (time, title) = match group.events.as_slice() {
+            [event] => (event.time.as_str(), event.title.as_str()),
+            _ => ("(multiple)", ""),
+        };
+
+        rows.push(DayRow {
+            date: group.label.clone(),
+            time,
+            duration: fmt_duration(group.total_duration),
+            title,
+        });
+
+        // List the individual events below the group
+        if expand && group.events.len() > 1 {
+            rows.extend(group.events.iter().map(|event| DayRow {
+                date: String::new(),
+                time: &event.time,
+                duration: event.duration.clone(),
+                title: &event.title,
+            }));
+        }
+    }
+
+    let total = groups.iter().map(|group| group.total_duration).sum();
+
+    let mut table = Table::new(rows.iter());
+    style_table(&mut table, table_style);
+    add_table_footers(&mut table, rows.len(), total, footers);
+    println!("{table}");
+}
+
+fn report_print_dry_run(summary: &ReportSummary) {
+    println!("Calendars:");
+    for (name, url) in summary.calendars.iter().zip(&summary.calendar_urls) {
+        println!("  {name}: {url}");
+    }
+
+    println!("Events fetched: {}", summary.fetched_event_count);
+    println!("Events after filtering: {}", summary.event_count);
+    println!("Total: {}", fmt_duration(summary.total_duration));
+}
+
+fn report_print_subtotals(calendars: &[String], events: &[EventSummary], table_style: TableStyle) {
+    #[derive(Tabled)]
+    struct Subtotal<'a> {
+        #[tabled(rename = "Calendar")]
+        name: &'a str,
+        #[tabled(rename = "Subtotal")]
+        total: String,
+    }
+
+    let subtotals = calendars
+        .iter()
+        .map(|calendar| Subtotal {
+            name: calendar,
+            total: fmt_duration(
+                events
+                    .iter()
+                    .filter(|event| event.calendar.eq(calendar))
+                    .map(|event| event.duration_sec)
+                    .sum(),
+            ),
+        })
+        .collect::<Vec<_>>();
+
+    let mut table = Table::new(subtotals.iter());
+    style_table(&mut table, table_style);
+    println!("{table}");
+}
+
+/// Print the total of every week, and how far it is over or under the target
+fn report_print_week_targets(weeks: &[EventGroup], target: i64, table_style: TableStyle) {
+    #[derive(Tabled)]
+    struct WeekRow<'a> {
+        #[tabled(rename = "Week")]
+        week: &'a str,
+        #[tabled(rename = "Total")]
+        total: String,
+        #[tabled(rename = "Over/Under")]
+        delta: String,
+    }
+
+    let rows = weeks
+        .iter()
+        .map(|week| {
+            let delta = week.total_duration - target;
+            WeekRow {
+                week: &week.label,
+                total: fmt_duration(week.total_duration),
+                delta: if delta < 0 {
+                    format!("⚠ {}", fmt_duration(delta))
+                } else {
+                    format!("+{}", fmt_duration(delta))
+                },
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let mut table = Table::new(rows.iter());
+    style_table(&mut table, table_style);
+    println!("{table}");
+}
+
+fn report_print_hourly(buckets: &[i64; 24], table_style: TableStyle) {
+    #[derive(Tabled)]
+    struct HourlyRow {
+        #[tabled(rename = "Hour")]
+        hour: String,
+        #[tabled(rename = "Minutes")]
+        minutes: i64,
+    }
+
+    let rows = buckets
+        .iter()
+        .enumerate()
+        .map(|(hour, secs)| HourlyRow {
+            hour: format!("{hour:02}:00 - {:02}:00", (hour + 1) % 24),
+            minutes: secs / 60,
+        })
+        .collect::<Vec<_>>();
+
+    let mut table = Table::new(rows.iter());
+    style_table(&mut table, table_style);
+    println!("{table}");
+}
+
+/// The width of the bar of the bucket with the most events
+const HISTOGRAM_WIDTH: usize = 40;
+
+/// Print the histogram as a bar chart, with bars proportional to the number of events
+fn report_print_histogram(buckets: &[HistogramBucket]) {
+    let labels = buckets
+        .iter()
+        .map(|bucket| match bucket.max_minutes {
+            Some(max_minutes) => format!(
+                "{}-{}",
+                fmt_minutes(bucket.min_minutes),
+                fmt_minutes(max_minutes)
+            ),
+            None => format!("{}+", fmt_minutes(bucket.min_minutes)),
+        })
+        .collect::<Vec<_>>();
+    let label_width = labels.iter().map(String::len).max().unwrap_or(0);
+
+    let max_count = buckets.iter().map(|bucket| bucket.count).max().unwrap_or(0);
+    let event_count = buckets.iter().map(|bucket| bucket.count).sum::<usize>();
+
+    for (label, bucket) in labels.iter().zip(buckets) {
+        let bar_width = if max_count == 0 {
+            0
+        } else {
+            bucket.count * HISTOGRAM_WIDTH / max_count
+        };
+        let percentage = if event_count == 0 {
+            0.0
+        } else {
+            bucket.count as f64 * 100.0 / event_count as f64
+        };
+
+        println!(
+            "{label:<label_width$} {:<HISTOGRAM_WIDTH$} {} ({percentage:.1}%) {}",
+            "#".repeat(bar_width),
+            bucket.count,
+            fmt_duration(bucket.total_duration)
+        );
+    }
+}
+
+/// Format minutes as e.g. `30m`, `1h` or `1h30m`
+fn fmt_minutes(minutes: i64) -> String {
+    match (minutes / 60, minutes % 60) {
+        (0, minutes) => format!("{minutes}m"),
+        (hours, 0) => format!("{hours}h"),
+        (hours, minutes) => format!("{hours}h{minutes}m"),
+    }
+}
+
+/// Calendar URL which reads the ICS file from stdin
+const STDIN_URL: &str = "-";
+/// Prefix of calendar URLs pointing to a local ICS file
+const FILE_URL_PREFIX: &str = "file://";
+
+/// The maximum time parsing a single ICS file may take
+const PARSE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Content types an ICS file may be served with
+const ICS_CONTENT_TYPES: &[&str] = &["text/calendar", "application/ics"];
+
+/// Sent when no User-Agent is configured
+const DEFAULT_USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+/// The number of redirects followed before giving up.
+/// Twice the default of reqwest, as some enterprise setups have long redirect chains
+const MAX_REDIRECTS: usize = 20;
+
+/// The client used for all calendar requests.
+/// Only set `verify_ssl` to false for calendars the user explicitly opted out for
+fn http_client(user_agent: Option<&str>, verify_ssl: bool) -> Result<Client> {
+    let client = Client::builder()
+        .user_agent(user_agent.unwrap_or(DEFAULT_USER_AGENT))
+        .danger_accept_invalid_certs(!verify_ssl)
+        .redirect(redirect::Policy::custom(|attempt| {
+            if attempt.previous().len() > MAX_REDIRECTS {
+                attempt.error(format!("More than {MAX_REDIRECTS} redirects"))
+            } else {
+                debug!("Redirected to {}", attempt.url());
+                attempt.follow()
+            }
+        }))
+        .build()?;
+
+    Ok(client)
+}
+
+/// Credentials sent along with the download of a calendar
+enum Credentials {
+    Basic { username: String, password: String },
+    Bearer(String),
+}
+
+/// Add the credentials, if any, to a request
+fn authorize(request: RequestBuilder, credentials: Option<&Credentials>) -> RequestBuilder {
+    match credentials {
+        Some(Credentials::Basic { username, password }) => {
+            request.basic_auth(username, Some(password))
+        }
+        Some(Credentials::Bearer(token)) => request.bearer_auth(token),
+        None => request,
+    }
+}
+
+/// Refresh a cached OAuth 2.0 access token when it expires within this many seconds
+const TOKEN_EXPIRY_MARGIN_SECS: i64 = 60;
+/// How long an access token is assumed to be valid if the token endpoint doesn't say
+const DEFAULT_TOKEN_LIFETIME_SECS: i64 = 3600;
+
+/// Get the credentials to download a calendar with.
+/// For OAuth 2.0 the cached access token is used, unless it (nearly) expired.
+/// In that case a new one is requested, which is returned as well so it can be cached
+#[instrument(skip_all, fields(calendar = %ics_config.name))]
+async fn resolve_credentials(
+    client: &Client,
+    ics_config: &ICalConfig,
+) -> Result<(Option<Credentials>, Option<CachedToken>)> {
+    let credentials = match &ics_config.auth {
+        None => None,
+        Some(AuthMethod::Basic { username, password }) => Some(Credentials::Basic {
+            username: username.clone(),
+            password: password.clone(),
+        }),
+        Some(AuthMethod::Bearer { token }) => Some(Credentials::Bearer(token.clone())),
+        Some(AuthMethod::OAuth2 {
+            client_id,
+            client_secret,
+            refresh_token,
+            token_endpoint,
+        }) => {
+            let valid_until = Utc::now() + Duration::seconds(TOKEN_EXPIRY_MARGIN_SECS);
+            match &ics_config.cached_token {
+                Some(token) if token.expires_at > valid_until => {
+                    Some(Credentials::Bearer(token.access_token.clone()))
+                }
+                _ => {
+                    let token = refresh_oauth2_token(
+                        client,
+                        client_id,
+                        client_secret.as_deref(),
+                        refresh_token,
+                        token_endpoint,
+                    )
+                    .await?;
+                    let credentials = Credentials::Bearer(token.access_token.clone());
+                    return Ok((Some(credentials), Some(token)));
+                }
+            }
+        }
+    };
+
+    Ok((credentials, None))
+}
+
+/// Request a new access token using the refresh token grant
+async fn refresh_oauth2_token(
+    client: &Client,
+    client_id: &str,
+    client_secret: Option<&str>,
+    refresh_token: &str,
+    token_endpoint: &str,
+) -> Result<CachedToken> {
+    #[derive(Deserialize)]
+    struct TokenResponse {
+        access_token: String,
+        expires_in: Option<i64>,
+    }
+
+    let mut form = vec![
+        ("grant_type", "refresh_token"),
+        ("refresh_token", refresh_token),
+        ("client_id", client_id),
+    ];
+    if let Some(client_secret) = client_secret {
+        form.push(("client_secret", client_secret));
+    }
+
+    let body = client
+        .post(token_endpoint)
+        .form(&form)
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+    let response: TokenResponse = serde_json::from_slice(&body)?;
+
+    Ok(CachedToken {
+        access_token: response.access_token,
+        expires_at: Utc::now()
+            + Duration::seconds(response.expires_in.unwrap_or(DEFAULT_TOKEN_LIFETIME_SECS)),
+    })
+}
+
+/// Download a calendar, unless it didn't change since the download the `validators` are from
+#[instrument(skip(client, url, credentials))]
+async fn download_ical(
+    client: &Client,
+    url: &str,
+    credentials: Option<&Credentials>,
+    force: bool,
+    validators: Option<Validators<'_>>,
+) -> Result<IcalResponse> {
+    let mut request = authorize(client.get(url), credentials);
+    if let Some(validators) = validators {
+        if let Some(etag) = validators.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = validators.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+
+    let response = request.send().await?.error_for_status()?;
+    let resolved_url = response.url().clone();
+    if response.status() == StatusCode::NOT_MODIFIED {
+        return Ok(IcalResponse::NotModified { url: resolved_url });
+    }
+
+    let header = |name: HeaderName| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_string())
+    };
+    let etag = header(ETAG);
+    let last_modified = header(LAST_MODIFIED);
+    let content_type_header = header(CONTENT_TYPE);
+
+    if !force {
+        // Strip parameters like the charset from the content type
+        let content_type = content_type_header
+            .as_deref()
+            .and_then(|value| value.split(';').next())
+            .map(|value| value.trim().to_lowercase());
+
+        match content_type {
+            Some(content_type) if ICS_CONTENT_TYPES.contains(&content_type.as_str()) => {}
+            Some(content_type) => {
+                return Err(AppError::Network(format!(
+                    "Server responded with content type '{content_type}' instead of an ICS file. Use --force to ignore this"
+                ))
+                .into())
+            }
+            None => {
+                return Err(AppError::Network(
+                    "Server responded without a content type. Use --force to ignore this"
+                        .to_string(),
+                )
+                .into())
+            }
+        }
+    }
+
+    let body_bytes = response.bytes().await?.to_vec();
+    let body_bytes = match content_type_header.as_deref().and_then(charset) {
+        // The parser only understands UTF-8
+        Some(encoding) if encoding != UTF_8 => {
+            let (text, _, had_errors) = encoding.decode(&body_bytes);
+            if had_errors {
+                warn!(
+                    "Calendar contains characters which are invalid in {}, they are replaced",
+                    encoding.name()
+                );
+            }
+
+            text.into_owned().into_bytes()
+        }
+        _ => body_bytes,
+    };
+
+    Ok(IcalResponse::Modified {
+        body: body_bytes,
+        url: resolved_url,
+        etag,
+        last_modified,
+    })
+}
+
+/// The headers of a cached download, to only download the calendar again if it changed
+#[derive(Debug, Clone, Copy)]
+struct Validators<'a> {
+    etag: Option<&'a str>,
+    last_modified: Option<&'a str>,
+}
+
+/// A downloaded calendar, with the URL it was downloaded from after redirects
+enum IcalResponse {
+    Modified {
+        body: Vec<u8>,
+        url: Url,
+        etag: Option<String>,
+        last_modified: Option<String>,
+    },
+    /// The calendar didn't change since the download the [Validators] are from
+    NotModified { url: Url },
+}
+
+/// Get the encoding from the charset parameter of a content type, e.g. `text/calendar; charset=ISO-8859-1`
+fn charset(content_type: &str) -> Option<&'static Encoding> {
+    content_type
+        .split(';')
+        .skip(1)
+        .filter_map(|param| param.split_once('='))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("charset"))
+        .and_then(|(_, value)| Encoding::for_label(value.trim().trim_matches('"').as_bytes()))
+}
+
+/// Requests the content type of every resource in a WebDAV collection
+const CALDAV_PROPFIND_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<d:propfind xmlns:d="DAV:">
+  <d:prop>
+    <d:getcontenttype/>
+  </d:prop>
+</d:propfind>"#;
+
+/// Download every ICS resource in a CalDAV calendar collection.
+/// The resources are concatenated, as an ICS file may contain multiple calendars.
+/// Also returns the URL of the collection after redirects
+#[instrument(skip_all)]
+async fn download_ical_caldav(
+    client: &Client,
+    url: &str,
+    username: &str,
+    password: &str,
+) -> Result<(Vec<u8>, Url)> {
+    // Discover the resources in the collection
+    let response = client
+        .request(Method::from_bytes(b"PROPFIND")?, url)
+        .basic_auth(username, Some(password))
+        .header("Depth", "1")
+        .header(CONTENT_TYPE, "application/xml; charset=utf-8")
+        .body(CALDAV_PROPFIND_BODY)
+        .send()
+        .await?
+        .error_for_status()?;
+    // The hrefs are relative to where the collection was redirected to
+    let collection_url = response.url().clone();
+    let multistatus = response.text().await?;
+
+    // The namespace prefix differs per server, e.g. `d:href` or `D:href`
+    let href_regex = Regex::new(r"<(?:[\w-]+:)?href>([^<]+)</(?:[\w-]+:)?href>")?;
+    let hrefs = href_regex
+        .captures_iter(&multistatus)
+        .map(|captures| captures[1].trim().replace("&amp;", "&"))
+        .filter(|href| href.ends_with(".ics"))
+        .collect::<Vec<_>>();
+
+    let mut body_bytes = Vec::new();
+    for href in hrefs {
+        let resource = client
+            .get(collection_url.join(&href)?)
+            .basic_auth(username, Some(password))
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+
+        body_bytes.extend_from_slice(&resource);
+        body_bytes.extend_from_slice(b"\r\n");
+    }
+
+    Ok((body_bytes, collection_url))
+}
+
+/// Read an ICS file from any reader, e.g. stdin or a local file
+#[instrument(skip_all)]
+async fn load_ical_from_reader(mut reader: impl AsyncRead + Unpin) -> Result<Vec<u8>> {
+    let mut body_bytes = Vec::new();
+    reader.read_to_end(&mut body_bytes).await?;
+
+    Ok(body_bytes)
+}
+
+/// Format a duration in seconds as HH:MM:SS, prefixed with `-` if negative
+pub fn fmt_duration(secs: i64) -> String {
+    // Division and modulo round towards zero, so format the magnitude
+    let sign = if secs < 0 { "-" } else { "" };
+    let secs = secs.unsigned_abs();
+
+    format!(
+        "{sign}{:02}:{:02}:{:02}",
+        (secs / 60) / 60,
+        (secs / 60) % 60,
+        secs % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::test_event;
+
+    #[test]
+    fn csv_round_trips_every_output_encoding() {
+        let events = [EventSummary {
+            title: "Café, €5".to_string(),
+            ..test_event("2024-03-01T09:00:00Z", "2024-03-01T10:30:00Z", "")
+        }];
+        let csv = csv::generate_csv(&events, true, None);
+
+        for encoding in [
+            OutputEncoding::Utf8,
+            OutputEncoding::Utf8Bom,
+            OutputEncoding::Latin1,
+        ] {
+            let bytes = encode_output(&csv, encoding);
+            let parsed = csv::parse_csv(&csv::decode_csv(&bytes)).unwrap();
+
+            assert_eq!(parsed.len(), 1, "{encoding:?}");
+            assert_eq!(parsed[0].title, "Café, €5", "{encoding:?}");
+            assert_eq!(parsed[0].duration_sec, 90 * 60, "{encoding:?}");
+        }
+    }
+
+    #[test]
+    fn fmt_duration_zero() {
+        assert_eq!(fmt_duration(0), "00:00:00");
+    }
+
+    #[test]
+    fn fmt_duration_negative() {
+        assert_eq!(fmt_duration(-1), "-00:00:01");
+        assert_eq!(fmt_duration(-90), "-00:01:30");
+        assert_eq!(fmt_duration(-(3600 + 61)), "-01:01:01");
+    }
+
+    #[test]
+    fn fmt_duration_over_a_day() {
+        assert_eq!(fmt_duration(25 * 3600 + 30 * 60), "25:30:00");
+        assert_eq!(fmt_duration(100 * 3600 + 59), "100:00:59");
+    }
+
+    #[test]
+    fn cap_per_day_shortens_last_events() {
+        let mut events = [
+            test_event("2024-03-01T09:00:00Z", "2024-03-01T13:00:00Z", ""),
+            test_event("2024-03-01T14:00:00Z", "2024-03-01T18:00:00Z", ""),
+            test_event("2024-03-02T09:00:00Z", "2024-03-02T10:00:00Z", ""),
+        ];
+        cap_per_day(&mut events, 6 * 3600);
+
+        assert_eq!(events[0].duration_sec, 4 * 3600);
+        assert_eq!(events[1].duration, "02:00:00");
+        assert_eq!(events[2].duration_sec, 3600);
+    }
+
+    #[test]
+    fn gap_rows_leave_out_surrounding_events() {
+        let events = [
+            EventSummary {
+                calendar: "Work".to_string(),
+                uid: Some("standup".to_string()),
+                ..test_event("2024-03-01T09:00:00Z", "2024-03-01T09:15:00Z", "Standup")
+            },
+            test_event("2024-03-01T10:00:00Z", "2024-03-01T11:00:00Z", "Review"),
+        ];
+        let (rows, gap_rows) = intersperse_gaps(&events, 30 * 60);
+
+        assert_eq!(gap_rows, [1]);
+        assert_eq!(rows[1].title, "(gap)");
+        assert_eq!(rows[1].duration, "GAP: 00:45:00");
+        assert!(rows[1].calendar.is_empty());
+        assert_eq!(rows[1].uid, None);
+    }
 }
@@ -0,0 +1,92 @@
+use crate::args::MarkdownDialect;
+use crate::event::EventSummary;
+use crate::{calc_total_duration, fmt_duration};
+
+/// Generate a Markdown table with a row for every event, followed by the total
+pub fn generate_markdown(
+    events: &[EventSummary],
+    header: bool,
+    dialect: MarkdownDialect,
+) -> String {
+    let mut buf = String::new();
+    if header {
+        push_header(&mut buf, &["Date", "Time", "Duration", "Title"], dialect);
+    }
+
+    for event in events {
+        push_row(
+            &mut buf,
+            &[&event.date, &event.time, &event.duration, &event.title],
+            dialect,
+        );
+    }
+
+    let total = fmt_duration(calc_total_duration(events));
+    push_row(
+        &mut buf,
+        &[&bold("Total", dialect), "", &bold(&total, dialect), ""],
+        dialect,
+    );
+
+    buf
+}
+
+fn push_header(buf: &mut String, cells: &[&str], dialect: MarkdownDialect) {
+    match dialect {
+        MarkdownDialect::Github | MarkdownDialect::Gitlab => {
+            push_row(buf, cells, dialect);
+            push_row(buf, &vec!["---"; cells.len()], dialect);
+        }
+        MarkdownDialect::Confluence => {
+            let row = cells
+                .iter()
+                .map(|cell| escape_cell(cell))
+                .collect::<Vec<_>>()
+                .join("||");
+
+            buf.push_str(&format!("||{row}||\n"));
+        }
+    }
+}
+
+fn push_row(buf: &mut String, cells: &[&str], dialect: MarkdownDialect) {
+    match dialect {
+        MarkdownDialect::Github | MarkdownDialect::Gitlab => {
+            let row = cells
+                .iter()
+                .map(|cell| escape_cell(cell))
+                .collect::<Vec<_>>()
+                .join(" | ");
+
+            buf.push_str(&format!("| {row} |\n"));
+        }
+        MarkdownDialect::Confluence => {
+            // Confluence merges empty cells into their neighbour, so they get a space
+            let row = cells
+                .iter()
+                .map(|cell| {
+                    if cell.is_empty() {
+                        " ".to_string()
+                    } else {
+                        escape_cell(cell)
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("|");
+
+            buf.push_str(&format!("|{row}|\n"));
+        }
+    }
+}
+
+fn bold(text: &str, dialect: MarkdownDialect) -> String {
+    match dialect {
+        MarkdownDialect::Github | MarkdownDialect::Gitlab => format!("**{text}**"),
+        MarkdownDialect::Confluence => format!("*{text}*"),
+    }
+}
+
+/// Escape characters which would end the cell, or the row
+fn escape_cell(cell: &str) -> String {
+    cell.replace('|', "\\|").replace(['\r', '\n'], " ")
+}
@@ -0,0 +1,96 @@
+use crate::event::EventSummary;
+use crate::{calc_total_duration, fmt_duration};
+use chrono::{DateTime, FixedOffset};
+
+/// Generate an Org-mode heading with a `LOGBOOK` drawer
+/// containing a `CLOCK` entry for every event
+pub fn generate_org(name: &str, events: &[EventSummary]) -> String {
+    let mut buf = format!("* {name}\n");
+    buf.push_str(":LOGBOOK:\n");
+
+    for event in events {
+        buf.push_str(&format!(
+            "CLOCK: [{}]--[{}] => {}\n",
+            fmt_timestamp(&event.local_start()),
+            fmt_timestamp(&event.local_end()),
+            fmt_clock_duration(event.duration_sec)
+        ));
+    }
+
+    buf.push_str(":END:\n");
+    buf.push_str(&format!(
+        "Total: {}\n",
+        fmt_clock_duration(calc_total_duration(events))
+    ));
+
+    buf
+}
+
+/// Generate an Org-mode table with a row for every event, followed by the total.
+/// The total is also given as a `TBLFM` formula, so Org recalculates it after editing the table
+pub fn generate_org_table(events: &[EventSummary], header: bool) -> String {
+    let mut buf = String::new();
+    if header {
+        push_row(&mut buf, &["Date", "Time", "Duration", "Title"]);
+    }
+    buf.push_str("|-\n");
+
+    for event in events {
+        push_row(
+            &mut buf,
+            &[&event.date, &event.time, &event.duration, &event.title],
+        );
+    }
+
+    buf.push_str("|-\n");
+    push_row(
+        &mut buf,
+        &["Total", "", &fmt_duration(calc_total_duration(events)), ""],
+    );
+    // Sum the durations between the first and second horizontal line, as a time
+    buf.push_str("#+TBLFM: @>$3=vsum(@I..@II);T\n");
+
+    buf
+}
+
+fn push_row(buf: &mut String, cells: &[&str]) {
+    let row = cells
+        .iter()
+        .map(|cell| escape_cell(cell))
+        .collect::<Vec<_>>()
+        .join(" | ");
+
+    buf.push_str(&format!("| {row} |\n"));
+}
+
+/// Replace characters which would end the cell, or the row
+fn escape_cell(cell: &str) -> String {
+    cell.replace('|', "\\vert{}").replace(['\r', '\n'], " ")
+}
+
+/// Format a timestamp as an Org-mode inactive timestamp body
+/// E.g. 2022-09-21 Wed 15:15
+fn fmt_timestamp(dt: &DateTime<FixedOffset>) -> String {
+    dt.format("%Y-%m-%d %a %H:%M").to_string()
+}
+
+/// Format a duration in seconds as HH:MM, the format Org uses for clocked time
+fn fmt_clock_duration(secs: i64) -> String {
+    // Division and modulo round towards zero, so format the magnitude
+    let sign = if secs < 0 { "-" } else { "" };
+    let secs = secs.unsigned_abs();
+
+    format!("{sign}{:02}:{:02}", (secs / 60) / 60, (secs / 60) % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fmt_clock_duration_negative() {
+        assert_eq!(fmt_clock_duration(0), "00:00");
+        assert_eq!(fmt_clock_duration(-90 * 60), "-01:30");
+        assert_eq!(fmt_clock_duration(26 * 3600), "26:00");
+    }
+}
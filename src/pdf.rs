@@ -1,208 +1,288 @@
-use crate::{calc_total_duration, fmt_duration, EventSummary};
+use crate::args::{Orientation, PaperSize, PdfLocaleName};
+use crate::error::AppError;
+use crate::event::EventSummary;
+use chrono::NaiveDate;
 use color_eyre::Result;
-use jni::{InitArgsBuilder, JNIEnv, JNIVersion, JavaVM};
 use std::env;
-use std::path::Path;
-use itext::itext::kernel::{PdfDocument, PdfWriter};
-use itext::itext::layout::{BlockElement, Border, Cell, Document, ElementPropertyContainer, HorizontalAlignment, Paragraph, RootElement, Table, TextAlignment};
-use itext::java::ByteArrayOutputStream;
-use tempfile::TempDir;
+use std::path::PathBuf;
 use tokio::fs;
 use tokio::io::AsyncWriteExt;
-use tokio::task::block_in_place;
-
-/// Java VM with jarfile dependencies
-struct DependentJavaVM {
-    /// The VM itself
-    javavm: JavaVM,
-    /// Directory containing jarfile dependencies
-    // Kept so that the tempdir continues to exist while
-    // the JVM is running
-    _tempdir: TempDir,
+use tracing::instrument;
+
+#[cfg(feature = "jvm-pdf")]
+mod jvm;
+#[cfg(feature = "pure-pdf")]
+mod pure;
+
+#[cfg(feature = "jvm-pdf")]
+use jvm as backend;
+#[cfg(feature = "pure-pdf")]
+use pure as backend;
+
+#[cfg(not(any(feature = "jvm-pdf", feature = "pure-pdf")))]
+compile_error!("Either the `jvm-pdf` or the `pure-pdf` feature must be enabled");
+
+#[cfg(all(feature = "jvm-pdf", feature = "pure-pdf"))]
+compile_error!(
+    "The `jvm-pdf` and `pure-pdf` features can't both be enabled, use `--no-default-features --features pure-pdf` for the pure Rust backend"
+);
+
+/// Details shown in the document header when the PDF is used for invoicing
+#[derive(Debug, Default)]
+pub struct InvoiceDetails {
+    /// Shown instead of the calendar name
+    pub client_name: Option<String>,
+    pub invoice_number: Option<String>,
+    pub invoice_date: Option<NaiveDate>,
+    pub cost_center: Option<String>,
 }
 
-impl DependentJavaVM {
-    /// Create a new JVM with jarfile dependencies
-    ///
-    /// # Errors
-    ///
-    /// - If saving a Jarfile to disk failed
-    /// - If creating the initialization arguments failed
-    /// - If creating the JVM failed
-    pub async fn new() -> Result<Self> {
-        let tempdir = TempDir::new()?;
-        let classpath = vec![
-            Self::write_jar(
-                tracing_slf4j::DEPENDENCIES,
-                "tracing_slf4j.jar",
-                tempdir.path(),
-            )
-            .await?,
-            Self::write_jar(itext::bundle::DEPENDENCIES, "itext.jar", tempdir.path()).await?,
-        ];
-
-        let args = InitArgsBuilder::new()
-            .version(JNIVersion::V8)
-            .option(format!("-Djava.class.path={}", classpath.join(":")))
-            //.option("-Xcheck:jni")
-            //.option("-verbose:jni")
-            .build()?;
-        let javavm = JavaVM::new(args)?;
-
-        Ok(Self {
-            javavm,
-            _tempdir: tempdir,
-        })
-    }
+/// The built-in PDF fonts, which don't have to be embedded in the document
+const STANDARD_FONTS: &[&str] = &[
+    "Courier",
+    "Courier-Bold",
+    "Courier-BoldOblique",
+    "Courier-Oblique",
+    "Helvetica",
+    "Helvetica-Bold",
+    "Helvetica-BoldOblique",
+    "Helvetica-Oblique",
+    "Symbol",
+    "Times-Bold",
+    "Times-BoldItalic",
+    "Times-Italic",
+    "Times-Roman",
+    "ZapfDingbats",
+];
+
+/// The labels used in the PDF, in a single language
+#[derive(Debug)]
+pub struct PdfLocale {
+    pub title: &'static str,
+    pub client: &'static str,
+    pub invoice_number: &'static str,
+    pub invoice_date: &'static str,
+    pub cost_center: &'static str,
+    pub date: &'static str,
+    pub time: &'static str,
+    pub duration: &'static str,
+    pub total: &'static str,
+    pub hour: &'static str,
+    pub minutes: &'static str,
+    pub invoice_title: &'static str,
+    pub due_date: &'static str,
+    pub rate: &'static str,
+    pub description: &'static str,
+    pub hours: &'static str,
+    pub amount: &'static str,
+    pub subtotal: &'static str,
+    pub vat: &'static str,
+    pub total_due: &'static str,
+}
 
-    /// Write a jarfile to disk
-    ///
-    /// # Errors
-    ///
-    /// if an IO error occurred
-    async fn write_jar(bytes: &[u8], name: &str, dir: &Path) -> Result<String> {
-        let path = dir.join(name);
-        let mut kernel = fs::File::create(&path).await?;
-        kernel.write_all(bytes).await?;
-
-        Ok(path.to_string_lossy().to_string())
+/// All supported locales. Adding a language only requires a new entry here
+/// and a variant in [PdfLocaleName]
+const LOCALES: &[(PdfLocaleName, PdfLocale)] = &[
+    (
+        PdfLocaleName::Nl,
+        PdfLocale {
+            title: "Urenregistratie",
+            client: "Klant:",
+            invoice_number: "Factuurnummer:",
+            invoice_date: "Factuurdatum:",
+            cost_center: "Kostenplaats:",
+            date: "Datum",
+            time: "Tijd",
+            duration: "Duratie",
+            total: "Totaal",
+            hour: "Uur",
+            minutes: "Minuten",
+            invoice_title: "Factuur",
+            due_date: "Vervaldatum:",
+            rate: "Uurtarief:",
+            description: "Omschrijving",
+            hours: "Uren",
+            amount: "Bedrag",
+            subtotal: "Subtotaal",
+            vat: "BTW",
+            total_due: "Te betalen",
+        },
+    ),
+    (
+        PdfLocaleName::En,
+        PdfLocale {
+            title: "Timesheet",
+            client: "Client:",
+            invoice_number: "Invoice number:",
+            invoice_date: "Invoice date:",
+            cost_center: "Cost center:",
+            date: "Date",
+            time: "Time",
+            duration: "Duration",
+            total: "Total",
+            hour: "Hour",
+            minutes: "Minutes",
+            invoice_title: "Invoice",
+            due_date: "Due date:",
+            rate: "Hourly rate:",
+            description: "Description",
+            hours: "Hours",
+            amount: "Amount",
+            subtotal: "Subtotal",
+            vat: "VAT",
+            total_due: "Total due",
+        },
+    ),
+    (
+        PdfLocaleName::De,
+        PdfLocale {
+            title: "Stundenzettel",
+            client: "Kunde:",
+            invoice_number: "Rechnungsnummer:",
+            invoice_date: "Rechnungsdatum:",
+            cost_center: "Kostenstelle:",
+            date: "Datum",
+            time: "Zeit",
+            duration: "Dauer",
+            total: "Gesamt",
+            hour: "Stunde",
+            minutes: "Minuten",
+            invoice_title: "Rechnung",
+            due_date: "Fälligkeitsdatum:",
+            rate: "Stundensatz:",
+            description: "Beschreibung",
+            hours: "Stunden",
+            amount: "Betrag",
+            subtotal: "Zwischensumme",
+            vat: "MwSt.",
+            total_due: "Gesamtbetrag",
+        },
+    ),
+    (
+        PdfLocaleName::Fr,
+        PdfLocale {
+            title: "Relevé d'heures",
+            client: "Client :",
+            invoice_number: "Numéro de facture :",
+            invoice_date: "Date de facture :",
+            cost_center: "Centre de coût :",
+            date: "Date",
+            time: "Heure",
+            duration: "Durée",
+            total: "Total",
+            hour: "Heure",
+            minutes: "Minutes",
+            invoice_title: "Facture",
+            due_date: "Date d'échéance :",
+            rate: "Taux horaire :",
+            description: "Description",
+            hours: "Heures",
+            amount: "Montant",
+            subtotal: "Sous-total",
+            vat: "TVA",
+            total_due: "Total à payer",
+        },
+    ),
+];
+
+impl PdfLocale {
+    pub fn get(name: PdfLocaleName) -> &'static Self {
+        LOCALES
+            .iter()
+            .find(|(locale_name, _)| *locale_name == name)
+            .map(|(_, locale)| locale)
+            .expect("Every PdfLocaleName has an entry in LOCALES")
     }
 }
 
-pub async fn generate_pdf(name: &str, events: &[EventSummary]) -> Result<()> {
-    let jvm = DependentJavaVM::new().await?;
-    let bytes = block_in_place(move || generate_pdf_inner(jvm, name, events))?;
-    let output_path = env::current_dir()?.join(format!("{name}.pdf"));
-    let mut file = fs::File::create(output_path).await?;
-    file.write_all(&bytes).await?;
+/// Options controlling the layout and contents of the PDF
+#[derive(Debug)]
+pub struct PdfOptions {
+    pub font_size: f32,
+    /// Name of one of the standard PDF fonts, e.g. Helvetica
+    pub font_family: String,
+    pub paper_size: PaperSize,
+    pub orientation: Orientation,
+    /// Time spent per hour of the day, shown as an extra section
+    pub hourly: Option<[i64; 24]>,
+    pub invoice: InvoiceDetails,
+    pub locale: &'static PdfLocale,
+    /// Show the description of every event, truncated to this many characters
+    pub description_length: Option<usize>,
+}
 
-    Ok(())
+/// A row of a table in a document rendered with [render_tables]
+pub enum PdfRow {
+    Cells { cells: Vec<String>, bold: bool },
+    Empty,
 }
 
-fn generate_pdf_inner(
-    jvm: DependentJavaVM,
+/// Generate the PDF in the current directory, returning its path
+#[instrument(skip(events, options))]
+pub async fn generate_pdf(
     name: &str,
     events: &[EventSummary],
-) -> Result<Vec<u8>> {
-    let mut env = jvm.javavm.attach_current_thread()?;
-
-    tracing_slf4j::register_log_fn(&mut env)?;
-
-    let byte_out = ByteArrayOutputStream::new(&mut env)?;
-    let pdf_writer = PdfWriter::new(&byte_out, &mut env)?;
-    let pdf_document = PdfDocument::new(&pdf_writer, &mut env)?;
-    let doc = Document::new(&pdf_document, &mut env)?;
-
-    doc.set_margins(40.0, 30.0, 40.0, 30.0, &mut env)?;
-
-    let header_table = Table::new(&[2.0, 2.0], &mut env)?;
-
-    // Document header
-
-    let cell = Cell::new(&mut env)?;
-    cell.add(
-        &Paragraph::new_with_text("Urenregistratie", &mut env)?,
-        &mut env,
-    )?;
-    cell.set_bold(&mut env)?;
-    cell.set_border(Border::NoBorder, &mut env)?;
-    cell.set_text_alignment(TextAlignment::Left, &mut env)?;
-
-    header_table.start_new_row(&mut env)?;
-    header_table.add_cell(&cell, &mut env)?;
-    header_table.add_cell(&get_empty_cell(Border::NoBorder, 24.0, &mut env)?, &mut env)?;
-
-    header_table.start_new_row(&mut env)?;
-    header_table.add_cell(&get_cell("Bedrijf:", Border::NoBorder, &mut env)?, &mut env)?;
-    header_table.add_cell(&get_cell(name, Border::NoBorder, &mut env)?, &mut env)?;
-
-    doc.add(&header_table, &mut env)?;
-
-    // Document content
-
-    let hour_table = Table::new(&[2.0, 2.0, 2.0], &mut env)?;
-    hour_table.set_horizontal_alignment(HorizontalAlignment::Center, &mut env)?;
-    hour_table.use_all_available_width(&mut env)?;
-
-    // Headers
-    hour_table.start_new_row(&mut env)?;
-    let cell = Cell::new(&mut env)?;
-    cell.add(&Paragraph::new_with_text("Datum", &mut env)?, &mut env)?;
-    cell.set_bold(&mut env)?;
-    cell.set_border(Border::NoBorder, &mut env)?;
-    hour_table.add_cell(&cell, &mut env)?;
-
-    let cell = Cell::new(&mut env)?;
-    cell.add(&Paragraph::new_with_text("Tijd", &mut env)?, &mut env)?;
-    cell.set_bold(&mut env)?;
-    cell.set_border(Border::NoBorder, &mut env)?;
-    hour_table.add_cell(&cell, &mut env)?;
-
-    let cell = Cell::new(&mut env)?;
-    cell.add(&Paragraph::new_with_text("Duratie", &mut env)?, &mut env)?;
-    cell.set_bold(&mut env)?;
-    cell.set_border(Border::NoBorder, &mut env)?;
-    hour_table.add_cell(&cell, &mut env)?;
-
-    for event in events {
-        hour_table.start_new_row(&mut env)?;
-        hour_table.add_cell(
-            &get_cell(&event.date, Border::NoBorder, &mut env)?,
-            &mut env,
-        )?;
-        hour_table.add_cell(
-            &get_cell(&event.time, Border::NoBorder, &mut env)?,
-            &mut env,
-        )?;
-        hour_table.add_cell(
-            &get_cell(&event.duration, Border::NoBorder, &mut env)?,
-            &mut env,
-        )?;
-    }
-
-    // Empty row
-    hour_table.start_new_row(&mut env)?;
-    hour_table.add_cell(&get_empty_cell(Border::NoBorder, 24.0, &mut env)?, &mut env)?;
-
-    // Totals
-    hour_table.start_new_row(&mut env)?;
-    hour_table.add_cell(&get_empty_cell(Border::NoBorder, 24.0, &mut env)?, &mut env)?;
-    hour_table.add_cell(&get_cell("Totaal", Border::NoBorder, &mut env)?, &mut env)?;
-    hour_table.add_cell(
-        &get_cell(
-            &fmt_duration(calc_total_duration(events)),
-            Border::NoBorder,
-            &mut env,
-        )?,
-        &mut env,
-    )?;
-
-    doc.add(&hour_table, &mut env)?;
-
-    // Export document
-
-    doc.close(&mut env)?;
-    let bytes = byte_out.to_byte_array(&mut env)?;
+    options: &PdfOptions,
+) -> Result<PathBuf> {
+    // Resolve the font before rendering, so a typo fails fast
+    let font_family = resolve_font(&options.font_family)?;
+    let bytes = backend::render(name, events, options, font_family).await?;
+    let output_path = env::current_dir()?.join(format!("{name}.pdf"));
+    let mut file = fs::File::create(&output_path).await?;
+    file.write_all(&bytes).await?;
 
-    Ok(bytes)
+    Ok(output_path)
 }
 
-fn get_empty_cell<'a>(border: Border, height: f32, env: &mut JNIEnv<'a>) -> Result<Cell<'a>> {
-    let cell = Cell::new(env)?;
-    cell.set_border(border, env)?;
+/// Render a document consisting only of tables, one after another.
+/// The cells of a row are divided equally over the width of the page
+#[instrument(skip_all)]
+pub async fn render_tables(tables: &[Vec<PdfRow>], options: &PdfOptions) -> Result<Vec<u8>> {
+    let font_family = resolve_font(&options.font_family)?;
+    backend::render_tables(tables, options, font_family).await
+}
 
-    let paragraph = Paragraph::new(env)?;
-    paragraph.set_height(height, env)?;
-    cell.add(&paragraph, env)?;
+/// The description of an event on a single line, shortened to at most `max_chars` characters
+fn truncate_description(description: &str, max_chars: usize) -> String {
+    let description = description.split_whitespace().collect::<Vec<_>>().join(" ");
+    if description.chars().count() <= max_chars {
+        return description;
+    }
 
-    Ok(cell)
+    let mut truncated = description
+        .chars()
+        .take(max_chars.saturating_sub(1))
+        .collect::<String>();
+    truncated.push('…');
+    truncated
 }
 
-fn get_cell<'a>(text: &str, border: Border, env: &mut JNIEnv<'a>) -> Result<Cell<'a>> {
-    let cell = Cell::new(env)?;
-    let paragraph = Paragraph::new_with_text(text, env)?;
-    cell.add(&paragraph, env)?;
-    cell.set_border(border, env)?;
+/// Find the standard font with this name, ignoring case
+fn resolve_font(font_family: &str) -> Result<&'static str> {
+    STANDARD_FONTS
+        .iter()
+        .copied()
+        .find(|font| font.eq_ignore_ascii_case(font_family))
+        .ok_or_else(|| {
+            AppError::InvalidArgs(format!(
+                "Unknown font '{font_family}'. Options are: {}",
+                STANDARD_FONTS.join(", ")
+            ))
+            .into()
+        })
+}
 
-    Ok(cell)
+/// The width and height of a page in points
+fn page_dimensions(paper_size: PaperSize, orientation: Orientation) -> (f32, f32) {
+    let (width, height) = match paper_size {
+        // 210 x 297 mm
+        PaperSize::A4 => (595.0, 842.0),
+        // 8.5 x 11 in
+        PaperSize::Letter => (612.0, 792.0),
+    };
+
+    match orientation {
+        Orientation::Portrait => (width, height),
+        Orientation::Landscape => (height, width),
+    }
 }
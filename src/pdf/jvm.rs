@@ -0,0 +1,429 @@
+use super::{page_dimensions, truncate_description, PdfOptions, PdfRow};
+use crate::event::EventSummary;
+use crate::{calc_total_duration, fmt_duration};
+use color_eyre::Result;
+use itext::itext::kernel::{PageSize, PdfDocument, PdfFontFactory, PdfWriter};
+use itext::itext::layout::{
+    BlockElement, Border, Cell, Document, ElementPropertyContainer, HorizontalAlignment, Paragraph,
+    RootElement, Table, TextAlignment,
+};
+use itext::java::ByteArrayOutputStream;
+use jni::{InitArgsBuilder, JNIEnv, JNIVersion, JavaVM};
+use std::path::Path;
+use tempfile::TempDir;
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+use tokio::task::block_in_place;
+
+/// Java VM with jarfile dependencies
+struct DependentJavaVM {
+    /// The VM itself
+    javavm: JavaVM,
+    /// Directory containing jarfile dependencies
+    // Kept so that the tempdir continues to exist while
+    // the JVM is running
+    _tempdir: TempDir,
+}
+
+impl DependentJavaVM {
+    /// Create a new JVM with jarfile dependencies
+    ///
+    /// # Errors
+    ///
+    /// - If saving a Jarfile to disk failed
+    /// - If creating the initialization arguments failed
+    /// - If creating the JVM failed
+    pub async fn new() -> Result<Self> {
+        let tempdir = TempDir::new()?;
+        let classpath = vec![
+            Self::write_jar(
+                tracing_slf4j::DEPENDENCIES,
+                "tracing_slf4j.jar",
+                tempdir.path(),
+            )
+            .await?,
+            Self::write_jar(itext::bundle::DEPENDENCIES, "itext.jar", tempdir.path()).await?,
+        ];
+
+        let args = InitArgsBuilder::new()
+            .version(JNIVersion::V8)
+            .option(format!("-Djava.class.path={}", classpath.join(":")))
+            //.option("-Xcheck:jni")
+            //.option("-verbose:jni")
+            .build()?;
+        let javavm = JavaVM::new(args)?;
+
+        Ok(Self {
+            javavm,
+            _tempdir: tempdir,
+        })
+    }
+
+    /// Write a jarfile to disk
+    ///
+    /// # Errors
+    ///
+    /// if an IO error occurred
+    async fn write_jar(bytes: &[u8], name: &str, dir: &Path) -> Result<String> {
+        let path = dir.join(name);
+        let mut kernel = fs::File::create(&path).await?;
+        kernel.write_all(bytes).await?;
+
+        Ok(path.to_string_lossy().to_string())
+    }
+}
+
+/// Render the PDF using iText, running in an embedded JVM
+pub async fn render(
+    name: &str,
+    events: &[EventSummary],
+    options: &PdfOptions,
+    font_family: &str,
+) -> Result<Vec<u8>> {
+    let jvm = DependentJavaVM::new().await?;
+    block_in_place(move || render_inner(jvm, name, events, options, font_family))
+}
+
+fn render_inner(
+    jvm: DependentJavaVM,
+    name: &str,
+    events: &[EventSummary],
+    options: &PdfOptions,
+    font_family: &str,
+) -> Result<Vec<u8>> {
+    let mut env = jvm.javavm.attach_current_thread()?;
+
+    tracing_slf4j::register_log_fn(&mut env)?;
+
+    let byte_out = ByteArrayOutputStream::new(&mut env)?;
+    let doc = open_document(&byte_out, options, font_family, &mut env)?;
+
+    let invoice = &options.invoice;
+    let locale = options.locale;
+
+    let header_table = Table::new(&[2.0, 2.0], &mut env)?;
+
+    // Document header
+
+    let cell = Cell::new(&mut env)?;
+    cell.add(&Paragraph::new_with_text(locale.title, &mut env)?, &mut env)?;
+    cell.set_bold(&mut env)?;
+    cell.set_border(Border::NoBorder, &mut env)?;
+    cell.set_text_alignment(TextAlignment::Left, &mut env)?;
+
+    header_table.start_new_row(&mut env)?;
+    header_table.add_cell(&cell, &mut env)?;
+    header_table.add_cell(&get_empty_cell(Border::NoBorder, 24.0, &mut env)?, &mut env)?;
+
+    header_table.start_new_row(&mut env)?;
+    header_table.add_cell(
+        &get_cell(locale.client, Border::NoBorder, &mut env)?,
+        &mut env,
+    )?;
+    header_table.add_cell(
+        &get_cell(
+            invoice.client_name.as_deref().unwrap_or(name),
+            Border::NoBorder,
+            &mut env,
+        )?,
+        &mut env,
+    )?;
+
+    if let Some(invoice_number) = &invoice.invoice_number {
+        header_table.start_new_row(&mut env)?;
+        header_table.add_cell(
+            &get_cell(locale.invoice_number, Border::NoBorder, &mut env)?,
+            &mut env,
+        )?;
+        header_table.add_cell(
+            &get_cell(invoice_number, Border::NoBorder, &mut env)?,
+            &mut env,
+        )?;
+    }
+
+    if let Some(invoice_date) = &invoice.invoice_date {
+        header_table.start_new_row(&mut env)?;
+        header_table.add_cell(
+            &get_cell(locale.invoice_date, Border::NoBorder, &mut env)?,
+            &mut env,
+        )?;
+        header_table.add_cell(
+            &get_cell(
+                &invoice_date.format("%d-%m-%Y").to_string(),
+                Border::NoBorder,
+                &mut env,
+            )?,
+            &mut env,
+        )?;
+    }
+
+    if let Some(cost_center) = &invoice.cost_center {
+        header_table.start_new_row(&mut env)?;
+        header_table.add_cell(
+            &get_cell(locale.cost_center, Border::NoBorder, &mut env)?,
+            &mut env,
+        )?;
+        header_table.add_cell(
+            &get_cell(cost_center, Border::NoBorder, &mut env)?,
+            &mut env,
+        )?;
+    }
+
+    doc.add(&header_table, &mut env)?;
+
+    // Document content
+
+    // Descriptions get a wider column of their own, in which iText wraps them
+    let column_widths: &[f32] = match options.description_length {
+        Some(_) => &[2.0, 2.0, 2.0, 4.0],
+        None => &[2.0, 2.0, 2.0],
+    };
+    let hour_table = Table::new(column_widths, &mut env)?;
+    hour_table.set_horizontal_alignment(HorizontalAlignment::Center, &mut env)?;
+    hour_table.use_all_available_width(&mut env)?;
+
+    // Headers
+    hour_table.start_new_row(&mut env)?;
+    let cell = Cell::new(&mut env)?;
+    cell.add(&Paragraph::new_with_text(locale.date, &mut env)?, &mut env)?;
+    cell.set_bold(&mut env)?;
+    cell.set_border(Border::NoBorder, &mut env)?;
+    hour_table.add_cell(&cell, &mut env)?;
+
+    let cell = Cell::new(&mut env)?;
+    cell.add(&Paragraph::new_with_text(locale.time, &mut env)?, &mut env)?;
+    cell.set_bold(&mut env)?;
+    cell.set_border(Border::NoBorder, &mut env)?;
+    hour_table.add_cell(&cell, &mut env)?;
+
+    let cell = Cell::new(&mut env)?;
+    cell.add(
+        &Paragraph::new_with_text(locale.duration, &mut env)?,
+        &mut env,
+    )?;
+    cell.set_bold(&mut env)?;
+    cell.set_border(Border::NoBorder, &mut env)?;
+    hour_table.add_cell(&cell, &mut env)?;
+
+    if options.description_length.is_some() {
+        let cell = Cell::new(&mut env)?;
+        cell.add(
+            &Paragraph::new_with_text(locale.description, &mut env)?,
+            &mut env,
+        )?;
+        cell.set_bold(&mut env)?;
+        cell.set_border(Border::NoBorder, &mut env)?;
+        hour_table.add_cell(&cell, &mut env)?;
+    }
+
+    for event in events {
+        hour_table.start_new_row(&mut env)?;
+        hour_table.add_cell(
+            &get_cell(&event.date, Border::NoBorder, &mut env)?,
+            &mut env,
+        )?;
+        hour_table.add_cell(
+            &get_cell(&event.time, Border::NoBorder, &mut env)?,
+            &mut env,
+        )?;
+        hour_table.add_cell(
+            &get_cell(&event.duration, Border::NoBorder, &mut env)?,
+            &mut env,
+        )?;
+
+        if let Some(description_length) = options.description_length {
+            hour_table.add_cell(
+                &get_cell(
+                    &truncate_description(&event.description, description_length),
+                    Border::NoBorder,
+                    &mut env,
+                )?,
+                &mut env,
+            )?;
+        }
+    }
+
+    // Empty row
+    hour_table.start_new_row(&mut env)?;
+    hour_table.add_cell(&get_empty_cell(Border::NoBorder, 24.0, &mut env)?, &mut env)?;
+
+    // Totals
+    hour_table.start_new_row(&mut env)?;
+    hour_table.add_cell(&get_empty_cell(Border::NoBorder, 24.0, &mut env)?, &mut env)?;
+    hour_table.add_cell(
+        &get_cell(locale.total, Border::NoBorder, &mut env)?,
+        &mut env,
+    )?;
+    hour_table.add_cell(
+        &get_cell(
+            &fmt_duration(calc_total_duration(events)),
+            Border::NoBorder,
+            &mut env,
+        )?,
+        &mut env,
+    )?;
+
+    doc.add(&hour_table, &mut env)?;
+
+    // Hourly breakdown
+
+    if let Some(hourly) = &options.hourly {
+        let hourly_table = Table::new(&[2.0, 2.0], &mut env)?;
+        hourly_table.set_horizontal_alignment(HorizontalAlignment::Center, &mut env)?;
+        hourly_table.use_all_available_width(&mut env)?;
+
+        hourly_table.start_new_row(&mut env)?;
+        hourly_table.add_cell(&get_empty_cell(Border::NoBorder, 24.0, &mut env)?, &mut env)?;
+
+        hourly_table.start_new_row(&mut env)?;
+        let cell = Cell::new(&mut env)?;
+        cell.add(&Paragraph::new_with_text(locale.hour, &mut env)?, &mut env)?;
+        cell.set_bold(&mut env)?;
+        cell.set_border(Border::NoBorder, &mut env)?;
+        hourly_table.add_cell(&cell, &mut env)?;
+
+        let cell = Cell::new(&mut env)?;
+        cell.add(
+            &Paragraph::new_with_text(locale.minutes, &mut env)?,
+            &mut env,
+        )?;
+        cell.set_bold(&mut env)?;
+        cell.set_border(Border::NoBorder, &mut env)?;
+        hourly_table.add_cell(&cell, &mut env)?;
+
+        for (hour, secs) in hourly.iter().enumerate() {
+            hourly_table.start_new_row(&mut env)?;
+            hourly_table.add_cell(
+                &get_cell(
+                    &format!("{hour:02}:00 - {:02}:00", (hour + 1) % 24),
+                    Border::NoBorder,
+                    &mut env,
+                )?,
+                &mut env,
+            )?;
+            hourly_table.add_cell(
+                &get_cell(&(secs / 60).to_string(), Border::NoBorder, &mut env)?,
+                &mut env,
+            )?;
+        }
+
+        doc.add(&hourly_table, &mut env)?;
+    }
+
+    // Export document
+
+    doc.close(&mut env)?;
+    let bytes = byte_out.to_byte_array(&mut env)?;
+
+    Ok(bytes)
+}
+
+/// Render a document consisting only of tables
+pub async fn render_tables(
+    tables: &[Vec<PdfRow>],
+    options: &PdfOptions,
+    font_family: &str,
+) -> Result<Vec<u8>> {
+    let jvm = DependentJavaVM::new().await?;
+    block_in_place(move || render_tables_inner(jvm, tables, options, font_family))
+}
+
+fn render_tables_inner(
+    jvm: DependentJavaVM,
+    tables: &[Vec<PdfRow>],
+    options: &PdfOptions,
+    font_family: &str,
+) -> Result<Vec<u8>> {
+    let mut env = jvm.javavm.attach_current_thread()?;
+
+    tracing_slf4j::register_log_fn(&mut env)?;
+
+    let byte_out = ByteArrayOutputStream::new(&mut env)?;
+    let doc = open_document(&byte_out, options, font_family, &mut env)?;
+
+    for rows in tables {
+        let table = Table::new(&vec![2.0; column_count(rows)], &mut env)?;
+        table.set_horizontal_alignment(HorizontalAlignment::Center, &mut env)?;
+        table.use_all_available_width(&mut env)?;
+
+        for row in rows {
+            table.start_new_row(&mut env)?;
+            match row {
+                PdfRow::Cells { cells, bold } => {
+                    for text in cells {
+                        let cell = get_cell(text, Border::NoBorder, &mut env)?;
+                        if *bold {
+                            cell.set_bold(&mut env)?;
+                        }
+                        table.add_cell(&cell, &mut env)?;
+                    }
+                }
+                PdfRow::Empty => {
+                    table.add_cell(&get_empty_cell(Border::NoBorder, 24.0, &mut env)?, &mut env)?;
+                }
+            }
+        }
+
+        doc.add(&table, &mut env)?;
+    }
+
+    doc.close(&mut env)?;
+    let bytes = byte_out.to_byte_array(&mut env)?;
+
+    Ok(bytes)
+}
+
+/// The number of cells in the widest row
+fn column_count(rows: &[PdfRow]) -> usize {
+    rows.iter()
+        .map(|row| match row {
+            PdfRow::Cells { cells, .. } => cells.len(),
+            PdfRow::Empty => 1,
+        })
+        .max()
+        .unwrap_or(1)
+}
+
+/// Create a document writing to `byte_out`, with the page size, margins and font applied
+fn open_document<'a>(
+    byte_out: &ByteArrayOutputStream<'a>,
+    options: &PdfOptions,
+    font_family: &str,
+    env: &mut JNIEnv<'a>,
+) -> Result<Document<'a>> {
+    let pdf_writer = PdfWriter::new(byte_out, env)?;
+    let pdf_document = PdfDocument::new(&pdf_writer, env)?;
+
+    let (width, height) = page_dimensions(options.paper_size, options.orientation);
+    let page_size = PageSize::new(width, height, env)?;
+    pdf_document.set_default_page_size(&page_size, env)?;
+    let doc = Document::new(&pdf_document, env)?;
+
+    doc.set_margins(40.0, 30.0, 40.0, 30.0, env)?;
+
+    let font = PdfFontFactory::create_font(font_family, env)?;
+    doc.set_font(&font, env)?;
+    doc.set_font_size(options.font_size, env)?;
+
+    Ok(doc)
+}
+
+fn get_empty_cell<'a>(border: Border, height: f32, env: &mut JNIEnv<'a>) -> Result<Cell<'a>> {
+    let cell = Cell::new(env)?;
+    cell.set_border(border, env)?;
+
+    let paragraph = Paragraph::new(env)?;
+    paragraph.set_height(height, env)?;
+    cell.add(&paragraph, env)?;
+
+    Ok(cell)
+}
+
+fn get_cell<'a>(text: &str, border: Border, env: &mut JNIEnv<'a>) -> Result<Cell<'a>> {
+    let cell = Cell::new(env)?;
+    let paragraph = Paragraph::new_with_text(text, env)?;
+    cell.add(&paragraph, env)?;
+    cell.set_border(border, env)?;
+
+    Ok(cell)
+}
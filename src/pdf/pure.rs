@@ -0,0 +1,267 @@
+use super::{page_dimensions, truncate_description, PdfOptions, PdfRow};
+use crate::event::EventSummary;
+use crate::{calc_total_duration, fmt_duration};
+use color_eyre::Result;
+use printpdf::{
+    BuiltinFont, IndirectFontRef, Mm, PdfDocument, PdfDocumentReference, PdfLayerReference, Pt,
+};
+
+/// Name of the one layer every page has
+const LAYER_NAME: &str = "Layer 1";
+/// Top and bottom margin, in points
+const MARGIN_VERTICAL: f32 = 40.0;
+/// Left and right margin, in points
+const MARGIN_HORIZONTAL: f32 = 30.0;
+/// Height of an empty row, in points
+const EMPTY_ROW_HEIGHT: f32 = 24.0;
+/// Height of a row relative to the font size
+const LINE_SPACING: f32 = 1.5;
+/// Width of an average character relative to the font size, used to estimate where to wrap text
+const AVERAGE_CHAR_WIDTH: f32 = 0.5;
+
+/// Render the PDF using printpdf, without requiring Java.
+/// The layout matches that of the JVM backend
+pub async fn render(
+    name: &str,
+    events: &[EventSummary],
+    options: &PdfOptions,
+    font_family: &str,
+) -> Result<Vec<u8>> {
+    let locale = options.locale;
+    let invoice = &options.invoice;
+
+    let mut writer = PageWriter::new(name, options, font_family)?;
+
+    // Document header
+
+    writer.row(&[locale.title, ""], true);
+    writer.row(
+        &[
+            locale.client,
+            invoice.client_name.as_deref().unwrap_or(name),
+        ],
+        false,
+    );
+
+    if let Some(invoice_number) = &invoice.invoice_number {
+        writer.row(&[locale.invoice_number, invoice_number], false);
+    }
+
+    if let Some(invoice_date) = &invoice.invoice_date {
+        writer.row(
+            &[
+                locale.invoice_date,
+                &invoice_date.format("%d-%m-%Y").to_string(),
+            ],
+            false,
+        );
+    }
+
+    if let Some(cost_center) = &invoice.cost_center {
+        writer.row(&[locale.cost_center, cost_center], false);
+    }
+
+    // Document content
+
+    writer.row(&[locale.date, locale.time, locale.duration], true);
+    for event in events {
+        writer.row(&[&event.date, &event.time, &event.duration], false);
+
+        // printpdf doesn't wrap text, so descriptions are wrapped onto lines below the event
+        if let Some(description_length) = options.description_length {
+            let description = truncate_description(&event.description, description_length);
+            for line in wrap_words(&description, writer.chars_per_line()) {
+                writer.row(&[&line], false);
+            }
+        }
+    }
+
+    writer.empty_row();
+    writer.row(
+        &["", locale.total, &fmt_duration(calc_total_duration(events))],
+        false,
+    );
+
+    // Hourly breakdown
+
+    if let Some(hourly) = &options.hourly {
+        writer.empty_row();
+        writer.row(&[locale.hour, locale.minutes], true);
+
+        for (hour, secs) in hourly.iter().enumerate() {
+            writer.row(
+                &[
+                    &format!("{hour:02}:00 - {:02}:00", (hour + 1) % 24),
+                    &(secs / 60).to_string(),
+                ],
+                false,
+            );
+        }
+    }
+
+    // Export document
+
+    Ok(writer.doc.save_to_bytes()?)
+}
+
+/// Render a document consisting only of tables
+pub async fn render_tables(
+    tables: &[Vec<PdfRow>],
+    options: &PdfOptions,
+    font_family: &str,
+) -> Result<Vec<u8>> {
+    let mut writer = PageWriter::new("", options, font_family)?;
+
+    for row in tables.iter().flatten() {
+        match row {
+            PdfRow::Cells { cells, bold } => {
+                writer.row(&cells.iter().map(String::as_str).collect::<Vec<_>>(), *bold)
+            }
+            PdfRow::Empty => writer.empty_row(),
+        }
+    }
+
+    Ok(writer.doc.save_to_bytes()?)
+}
+
+/// Writes rows of text top to bottom, starting a new page when the current one is full
+struct PageWriter {
+    doc: PdfDocumentReference,
+    layer: PdfLayerReference,
+    font: IndirectFontRef,
+    bold: IndirectFontRef,
+    font_size: f32,
+    width: f32,
+    height: f32,
+    /// Baseline of the last written row, in points from the bottom of the page
+    y: f32,
+}
+
+impl PageWriter {
+    /// Create a document with a single, empty, page
+    fn new(title: &str, options: &PdfOptions, font_family: &str) -> Result<Self> {
+        let (width, height) = page_dimensions(options.paper_size, options.orientation);
+        let (doc, page, layer) =
+            PdfDocument::new(title, pt_to_mm(width), pt_to_mm(height), LAYER_NAME);
+
+        let (regular, bold) = builtin_fonts(font_family);
+        let font = doc.add_builtin_font(regular)?;
+        let bold = doc.add_builtin_font(bold)?;
+
+        Ok(Self {
+            layer: doc.get_page(page).get_layer(layer),
+            doc,
+            font,
+            bold,
+            font_size: options.font_size,
+            width,
+            height,
+            y: height - MARGIN_VERTICAL,
+        })
+    }
+
+    /// Write a row, dividing the available width equally over the cells
+    fn row(&mut self, cells: &[&str], bold: bool) {
+        self.advance(self.font_size * LINE_SPACING);
+
+        let font = if bold { &self.bold } else { &self.font };
+        let column_width = (self.width - 2.0 * MARGIN_HORIZONTAL) / cells.len() as f32;
+        for (idx, text) in cells.iter().enumerate() {
+            let x = MARGIN_HORIZONTAL + column_width * idx as f32;
+            self.layer.use_text(
+                *text,
+                f64::from(self.font_size),
+                pt_to_mm(x),
+                pt_to_mm(self.y),
+                font,
+            );
+        }
+    }
+
+    /// Roughly the number of characters which fit on a single row
+    fn chars_per_line(&self) -> usize {
+        ((self.width - 2.0 * MARGIN_HORIZONTAL) / (self.font_size * AVERAGE_CHAR_WIDTH)) as usize
+    }
+
+    fn empty_row(&mut self) {
+        self.advance(EMPTY_ROW_HEIGHT);
+    }
+
+    /// Move down by `height` points, continuing on a new page if that doesn't fit
+    fn advance(&mut self, height: f32) {
+        if self.y - height < MARGIN_VERTICAL {
+            let (page, layer) =
+                self.doc
+                    .add_page(pt_to_mm(self.width), pt_to_mm(self.height), LAYER_NAME);
+            self.layer = self.doc.get_page(page).get_layer(layer);
+            self.y = self.height - MARGIN_VERTICAL;
+        }
+
+        self.y -= height;
+    }
+}
+
+/// Split text into lines of at most `width` characters, breaking between words where possible
+fn wrap_words(text: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let mut lines = Vec::new();
+    let mut line = String::new();
+
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > width {
+            lines.push(std::mem::take(&mut line));
+        }
+
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+
+        // Words longer than a line are broken up
+        while line.chars().count() > width {
+            let rest = line.chars().skip(width).collect::<String>();
+            lines.push(line.chars().take(width).collect());
+            line = rest;
+        }
+    }
+
+    if !line.is_empty() {
+        lines.push(line);
+    }
+
+    lines
+}
+
+fn pt_to_mm(points: f32) -> Mm {
+    Mm::from(Pt(f64::from(points)))
+}
+
+/// The regular and bold variant of one of the standard fonts.
+/// The bold variant is used for headers
+fn builtin_fonts(font_family: &str) -> (BuiltinFont, BuiltinFont) {
+    match font_family {
+        "Courier" => (BuiltinFont::Courier, BuiltinFont::CourierBold),
+        "Courier-Bold" => (BuiltinFont::CourierBold, BuiltinFont::CourierBold),
+        "Courier-BoldOblique" => (
+            BuiltinFont::CourierBoldOblique,
+            BuiltinFont::CourierBoldOblique,
+        ),
+        "Courier-Oblique" => (BuiltinFont::CourierOblique, BuiltinFont::CourierBoldOblique),
+        "Helvetica-Bold" => (BuiltinFont::HelveticaBold, BuiltinFont::HelveticaBold),
+        "Helvetica-BoldOblique" => (
+            BuiltinFont::HelveticaBoldOblique,
+            BuiltinFont::HelveticaBoldOblique,
+        ),
+        "Helvetica-Oblique" => (
+            BuiltinFont::HelveticaOblique,
+            BuiltinFont::HelveticaBoldOblique,
+        ),
+        "Symbol" => (BuiltinFont::Symbol, BuiltinFont::Symbol),
+        "Times-Bold" => (BuiltinFont::TimesBold, BuiltinFont::TimesBold),
+        "Times-BoldItalic" => (BuiltinFont::TimesBoldItalic, BuiltinFont::TimesBoldItalic),
+        "Times-Italic" => (BuiltinFont::TimesItalic, BuiltinFont::TimesBoldItalic),
+        "Times-Roman" => (BuiltinFont::TimesRoman, BuiltinFont::TimesBold),
+        "ZapfDingbats" => (BuiltinFont::ZapfDingbats, BuiltinFont::ZapfDingbats),
+        _ => (BuiltinFont::Helvetica, BuiltinFont::HelveticaBold),
+    }
+}
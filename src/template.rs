@@ -0,0 +1,40 @@
+use crate::event::EventSummary;
+use crate::{calc_total_duration, fmt_duration};
+use color_eyre::Result;
+use handlebars::Handlebars;
+use serde_json::json;
+
+/// Passing this as the template path uses the bundled template
+pub const DEFAULT_TEMPLATE_NAME: &str = "default";
+
+/// Template mirroring the standard table view
+const DEFAULT_TEMPLATE: &str = include_str!("../templates/default.hbs");
+
+/// Render the report using a Handlebars template.
+///
+/// The template receives the report `name`, all `events` with every field of [EventSummary],
+/// and the `total` (HH:MM:SS) and `total_sec` duration.
+///
+/// # Errors
+///
+/// If the template is invalid
+pub fn render_template(
+    template: Option<&str>,
+    name: &str,
+    events: &[EventSummary],
+) -> Result<String> {
+    let mut handlebars = Handlebars::new();
+    // The output isn't HTML, so nothing needs to be escaped
+    handlebars.register_escape_fn(handlebars::no_escape);
+
+    let total = calc_total_duration(events);
+    let context = json!({
+        "name": name,
+        "events": events,
+        "total": fmt_duration(total),
+        "total_sec": total,
+    });
+
+    let rendered = handlebars.render_template(template.unwrap_or(DEFAULT_TEMPLATE), &context)?;
+    Ok(rendered)
+}
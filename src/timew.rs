@@ -0,0 +1,39 @@
+use crate::event::EventSummary;
+use chrono::{DateTime, FixedOffset};
+
+/// Generate a `timew track` command for every event, to import them into Timewarrior.
+/// The calendar name and the title of the event are used as tags
+pub fn generate_timew(events: &[EventSummary]) -> String {
+    let mut buf = String::new();
+    for event in events {
+        let tags = [&event.calendar, &event.title]
+            .into_iter()
+            .filter(|tag| !tag.is_empty())
+            .map(|tag| quote(tag))
+            .collect::<Vec<_>>();
+
+        buf.push_str(&format!(
+            "timew track {} - {}",
+            fmt_timestamp(&event.local_start()),
+            fmt_timestamp(&event.local_end())
+        ));
+        for tag in tags {
+            buf.push(' ');
+            buf.push_str(&tag);
+        }
+        buf.push('\n');
+    }
+
+    buf
+}
+
+/// Format a timestamp as ISO 8601 with the offset it is displayed in, e.g. 2022-09-21T15:15:30+02:00.
+/// Timewarrior converts it to UTC itself, so the times match the report's timezone
+fn fmt_timestamp(dt: &DateTime<FixedOffset>) -> String {
+    dt.format("%Y-%m-%dT%H:%M:%S%:z").to_string()
+}
+
+/// Quote a tag for the shell, so it stays a single argument
+fn quote(tag: &str) -> String {
+    format!("'{}'", tag.replace('\'', r"'\''"))
+}